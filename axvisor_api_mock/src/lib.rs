@@ -0,0 +1,229 @@
+// Copyright 2025 The Axvisor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Link-time implementations of all [`axvisor_api`] traits dispatching to
+//! their mocks, for the tests of components.
+//!
+//! The mocks generated with the `mock` feature of `axvisor_api` only
+//! implement the API traits in the tests of `axvisor_api` itself, so enabling
+//! the feature never clashes with the real implementations of a binary. The
+//! tests of other crates link this crate instead, which implements every API
+//! function by calling the mock of the trait:
+//!
+//! ```toml
+//! [dev-dependencies]
+//! axvisor_api = { version = "0.2", features = ["mock"] }
+//! axvisor_api_mock = "0.2"
+//! ```
+//!
+//! As nothing is referenced from this crate, it must be linked explicitly,
+//! e.g. in the root of the crate under test:
+//!
+//! ```rust,ignore
+//! #[cfg(test)]
+//! extern crate axvisor_api_mock;
+//! ```
+//!
+//! The mocks are then controlled as described in [`axvisor_api::mock`].
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+
+use axaddrspace::GuestPhysAddr;
+use axvisor_api::{
+    arch::ArchIf,
+    host::{HostIf, NumaNodeId},
+    iommu::{DmaPerm, DomainId, IommuError, IommuFault, IommuIf, StreamId},
+    memory::{
+        AllocError, HostMemoryRegions, MemAttr, MemoryIf, PageSize, PhysAddr, PlacementHint,
+        VirtAddr,
+    },
+    time::{CancelToken, Nanos, Ticks, TimeIf, TimeValue},
+    vmm::{
+        DirtyBitmap, DirtyLogError, GuestAccess, GuestMemoryError, InterruptVector, VCpuId, VMId,
+        VmmIf,
+    },
+};
+
+/// Implement an API trait for `$name` by calling the same function of
+/// `$mock`, the mock implementation of the trait.
+///
+/// The `unsafe fn` items are listed in a trailing `unsafe` block.
+macro_rules! forward {
+    (
+        impl $trait:ident for $name:ident as $mock:path {
+            $(
+                $(#[$attr:meta])*
+                fn $fn_name:ident($($arg:ident: $ty:ty),* $(,)?) $(-> $ret:ty)?;
+            )*
+            $(
+                unsafe {
+                    $(
+                        $(#[$unsafe_attr:meta])*
+                        fn $unsafe_fn_name:ident(
+                            $($unsafe_arg:ident: $unsafe_ty:ty),* $(,)?
+                        ) $(-> $unsafe_ret:ty)?;
+                    )*
+                }
+            )?
+        }
+    ) => {
+        struct $name;
+
+        #[axvisor_api::api_impl]
+        impl $trait for $name {
+            $(
+                $(#[$attr])*
+                fn $fn_name($($arg: $ty),*) $(-> $ret)? {
+                    <$mock as $trait>::$fn_name($($arg),*)
+                }
+            )*
+            $($(
+                $(#[$unsafe_attr])*
+                unsafe fn $unsafe_fn_name($($unsafe_arg: $unsafe_ty),*) $(-> $unsafe_ret)? {
+                    unsafe { <$mock as $trait>::$unsafe_fn_name($($unsafe_arg),*) }
+                }
+            )*)?
+        }
+    };
+}
+
+forward! {
+    impl ArchIf for ArchIfMock as axvisor_api::arch::mock::Mock {
+        #[cfg(target_arch = "aarch64")]
+        fn hardware_inject_virtual_interrupt(vector: InterruptVector);
+        #[cfg(target_arch = "aarch64")]
+        fn read_vgicd_typer() -> u32;
+        #[cfg(target_arch = "aarch64")]
+        fn read_vgicd_iidr() -> u32;
+        #[cfg(target_arch = "aarch64")]
+        fn get_host_gicd_base() -> PhysAddr;
+        #[cfg(target_arch = "aarch64")]
+        fn get_host_gicr_base() -> PhysAddr;
+    }
+}
+
+forward! {
+    impl HostIf for HostIfMock as axvisor_api::host::mock::Mock {
+        fn get_host_cpu_num() -> usize;
+        fn get_host_numa_node_num() -> usize;
+        fn get_host_cpu_numa_node(cpu_id: usize) -> NumaNodeId;
+        fn get_host_numa_node_free_memory(node: NumaNodeId) -> Option<usize>;
+    }
+}
+
+forward! {
+    impl IommuIf for IommuIfMock as axvisor_api::iommu::mock::Mock {
+        fn create_domain() -> Result<DomainId, IommuError>;
+        fn destroy_domain(domain: DomainId) -> Result<(), IommuError>;
+        fn map(
+            domain: DomainId,
+            gpa: GuestPhysAddr,
+            hpa: PhysAddr,
+            size: usize,
+            perm: DmaPerm,
+        ) -> Result<(), IommuError>;
+        fn unmap(domain: DomainId, gpa: GuestPhysAddr, size: usize) -> Result<(), IommuError>;
+        fn attach_device(domain: DomainId, stream_id: StreamId) -> Result<(), IommuError>;
+        fn detach_device(domain: DomainId, stream_id: StreamId) -> Result<(), IommuError>;
+        fn next_fault() -> Option<IommuFault>;
+    }
+}
+
+forward! {
+    impl MemoryIf for MemoryIfMock as axvisor_api::memory::mock::Mock {
+        fn alloc_frame() -> Option<PhysAddr>;
+        fn alloc_contiguous_frames(num_frames: usize, frame_align_pow2: usize) -> Option<PhysAddr>;
+        fn try_alloc_frame() -> Result<PhysAddr, AllocError>;
+        fn alloc_frame_with_hint(hint: PlacementHint) -> Result<PhysAddr, AllocError>;
+        fn alloc_zeroed_frame() -> Option<PhysAddr>;
+        fn try_alloc_contiguous_frames(
+            num_frames: usize,
+            frame_align_pow2: usize,
+        ) -> Result<PhysAddr, AllocError>;
+        fn alloc_contiguous_frames_with_hint(
+            num_frames: usize,
+            frame_align_pow2: usize,
+            hint: PlacementHint,
+        ) -> Result<PhysAddr, AllocError>;
+        fn alloc_zeroed_contiguous_frames(
+            num_frames: usize,
+            frame_align_pow2: usize,
+        ) -> Option<PhysAddr>;
+        fn alloc_frames_of_size(size: PageSize) -> Result<PhysAddr, AllocError>;
+        fn phys_to_virt(addr: PhysAddr) -> VirtAddr;
+        fn virt_to_phys(addr: VirtAddr) -> PhysAddr;
+        fn try_phys_to_virt(addr: PhysAddr) -> Option<VirtAddr>;
+        fn try_virt_to_phys(addr: VirtAddr) -> Option<PhysAddr>;
+        fn try_phys_to_virt_range(addr: PhysAddr, len: usize) -> Option<VirtAddr>;
+        fn try_virt_to_phys_range(addr: VirtAddr, len: usize) -> Option<PhysAddr>;
+        fn host_memory_regions() -> HostMemoryRegions;
+        fn map_mmio(paddr: PhysAddr, size: usize, attr: MemAttr) -> Option<VirtAddr>;
+        unsafe {
+            fn dealloc_frame(addr: PhysAddr);
+            fn dealloc_contiguous_frames(first_addr: PhysAddr, num_frames: usize);
+            fn dealloc_frames_of_size(addr: PhysAddr, size: PageSize);
+            fn unmap_mmio(vaddr: VirtAddr, size: usize);
+        }
+    }
+}
+
+forward! {
+    impl TimeIf for TimeIfMock as axvisor_api::time::mock::Mock {
+        fn current_ticks() -> Ticks;
+        fn ticks_to_nanos(ticks: Ticks) -> Nanos;
+        fn nanos_to_ticks(nanos: Nanos) -> Ticks;
+        fn register_timer(
+            deadline: TimeValue,
+            callback: Box<dyn FnOnce(TimeValue) + Send + 'static>,
+        ) -> CancelToken;
+        fn cancel_timer(token: CancelToken);
+    }
+}
+
+forward! {
+    impl VmmIf for VmmIfMock as axvisor_api::vmm::mock::Mock {
+        fn current_vm_id() -> VMId;
+        fn current_vcpu_id() -> VCpuId;
+        fn vcpu_num(vm_id: VMId) -> Option<usize>;
+        fn active_vcpus(vm_id: VMId) -> Option<usize>;
+        fn inject_interrupt(vm_id: VMId, vcpu_id: VCpuId, vector: InterruptVector);
+        fn notify_vcpu_timer_expired(vm_id: VMId, vcpu_id: VCpuId);
+        fn translate_guest_phys(
+            vm_id: VMId,
+            gpa: GuestPhysAddr,
+            access: GuestAccess,
+        ) -> Result<PhysAddr, GuestMemoryError>;
+        fn map_guest_page(
+            vm_id: VMId,
+            gpa: GuestPhysAddr,
+            hpa: PhysAddr,
+        ) -> Result<(), GuestMemoryError>;
+        fn unmap_guest_page(vm_id: VMId, gpa: GuestPhysAddr) -> Result<(), GuestMemoryError>;
+        fn balloon_target_changed(vm_id: VMId, target_pages: usize);
+        fn enable_dirty_log(vm_id: VMId) -> Result<(), DirtyLogError>;
+        fn disable_dirty_log(vm_id: VMId) -> Result<(), DirtyLogError>;
+        fn fetch_and_clear_dirty_log(
+            vm_id: VMId,
+            gpa: GuestPhysAddr,
+            num_pages: usize,
+        ) -> Result<DirtyBitmap, DirtyLogError>;
+    }
+}
+
+#[cfg(test)]
+mod test;
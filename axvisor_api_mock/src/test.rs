@@ -0,0 +1,197 @@
+// Copyright 2025 The Axvisor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+extern crate std;
+
+use memory_addr::{pa, va};
+
+#[test]
+fn test_forwarding() {
+    use axvisor_api::{memory, vmm};
+
+    let _fixture = axvisor_api::mock::fixture();
+    axvisor_api::assert_all_apis_implemented!();
+
+    memory::mock::alloc_frame.set(|()| Some(pa!(0x1000)));
+    assert_eq!(memory::alloc_frame(), Some(pa!(0x1000)));
+    // default bodies call the other API functions through their mocks
+    assert_eq!(memory::try_alloc_frame(), Ok(pa!(0x1000)));
+    assert_eq!(memory::mock::alloc_frame.call_count(), 2);
+    assert_eq!(memory::mock::try_alloc_frame.call_count(), 1);
+
+    unsafe { memory::dealloc_frame(pa!(0x1000)) };
+    assert_eq!(memory::mock::dealloc_frame.calls(), [(pa!(0x1000),)]);
+
+    vmm::mock::current_vm_id.set(|()| 3);
+    assert_eq!(vmm::current_vm_id(), 3);
+}
+
+#[test]
+fn test_mock_memory() {
+    use axvisor_api::memory;
+
+    let _fixture = axvisor_api::mock::fixture();
+
+    memory::mock::alloc_frame.set(|()| Some(pa!(0x1000)));
+    assert_eq!(memory::alloc_frame(), Some(pa!(0x1000)));
+    assert_eq!(memory::alloc_frame(), Some(pa!(0x1000)));
+    assert_eq!(memory::mock::alloc_frame.call_count(), 2);
+
+    memory::mock::phys_to_virt.set(|(addr,)| va!(addr.as_usize() + 0x8000));
+    assert_eq!(memory::phys_to_virt(pa!(0x1000)), va!(0x9000));
+    assert_eq!(memory::mock::phys_to_virt.calls(), [(pa!(0x1000),)]);
+
+    // unmocked functions return the default value
+    assert_eq!(memory::alloc_contiguous_frames(4, 0), None);
+
+    memory::mock::reset();
+    assert_eq!(memory::mock::alloc_frame.call_count(), 0);
+    assert_eq!(memory::alloc_frame(), None);
+}
+
+#[test]
+fn test_mock_ioremap() {
+    use axvisor_api::memory::{self, MemAttr};
+    use std::vec;
+
+    let _fixture = axvisor_api::mock::fixture();
+
+    let mut registers = vec![0u64; 4];
+    let base = va!(registers.as_mut_ptr() as usize);
+    memory::mock::map_mmio.set(move |_| Some(base));
+
+    let region = memory::ioremap(pa!(0x800_0000), 0x20, MemAttr::DeviceStrict).unwrap();
+    assert_eq!(
+        memory::mock::map_mmio.calls(),
+        [(pa!(0x800_0000), 0x20, MemAttr::DeviceStrict)]
+    );
+    region.write(0x8, 0x1234_5678u32);
+    region.write(0x10, u64::MAX);
+    assert_eq!(region.read::<u32>(0x8), 0x1234_5678);
+    assert_eq!(region.read::<u8>(0x17), 0xff);
+
+    let result = std::panic::catch_unwind(|| region.read::<u32>(0x1e));
+    assert!(result.is_err());
+    let result = std::panic::catch_unwind(|| region.read::<u32>(0x20));
+    assert!(result.is_err());
+
+    drop(region);
+    assert_eq!(memory::mock::unmap_mmio.calls(), [(base, 0x20)]);
+    assert_eq!(registers[1] as u32, 0x1234_5678);
+
+    memory::mock::map_mmio.reset();
+    assert!(memory::ioremap(pa!(0x800_0000), 0x20, MemAttr::Device).is_none());
+}
+
+#[test]
+fn test_mock_shared_phys_frame_duplicate() {
+    use axvisor_api::memory::{self, SharedPhysFrame, frame_table};
+    use std::vec;
+
+    let _fixture = axvisor_api::mock::fixture();
+
+    let frame = vec![0u8; 0x1000];
+    let base = pa!(frame.as_ptr() as usize);
+    // a broken allocator returning the same frame twice
+    memory::mock::alloc_frame.set(move |()| Some(base));
+
+    let first = SharedPhysFrame::alloc().unwrap();
+    let result = std::panic::catch_unwind(SharedPhysFrame::alloc);
+    assert!(result.is_err());
+    assert_eq!(frame_table::ref_count(base), 1);
+
+    drop(first);
+    assert_eq!(memory::mock::dealloc_frame.calls(), [(base,)]);
+    assert!(frame_table::snapshot().is_empty());
+}
+
+#[test]
+fn test_mock_iommu_unsupported() {
+    use axaddrspace::GuestPhysAddr;
+    use axvisor_api::iommu::{self, DmaPerm, IommuError};
+
+    let _fixture = axvisor_api::mock::fixture();
+
+    // Without behaviors, the mocks fall back to the default bodies.
+    assert_eq!(iommu::create_domain(), Err(IommuError::Unsupported));
+    assert_eq!(
+        iommu::map(
+            1,
+            GuestPhysAddr::from_usize(0x8000_0000),
+            pa!(0x4_0000_0000),
+            0x1000,
+            DmaPerm::READ,
+        ),
+        Err(IommuError::Unsupported)
+    );
+    assert_eq!(iommu::next_fault(), None);
+}
+
+#[test]
+fn test_mock_expectations() {
+    use axvisor_api::vmm;
+
+    let _fixture = axvisor_api::mock::fixture();
+
+    vmm::mock::inject_interrupt
+        .expect(|&(vm_id, _, vector)| vm_id == 1 && vector == 0x20)
+        .times(2);
+    vmm::mock::inject_interrupt.expect(|&(vm_id, _, _)| vm_id == 2);
+
+    vmm::inject_interrupt(1, 0, 0x20);
+    vmm::inject_interrupt(2, 1, 0x30);
+    vmm::inject_interrupt(1, 1, 0x20);
+    vmm::mock::verify();
+
+    let result = std::panic::catch_unwind(|| vmm::inject_interrupt(1, 0, 0x20));
+    assert!(result.is_err());
+    let result = std::panic::catch_unwind(|| vmm::inject_interrupt(3, 0, 0x20));
+    assert!(result.is_err());
+
+    vmm::mock::reset();
+    vmm::mock::notify_vcpu_timer_expired.expect(|_| true);
+    let result = std::panic::catch_unwind(vmm::mock::verify);
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_mock_non_clone_args() {
+    use axvisor_api::time;
+    use core::time::Duration;
+    use std::{
+        boxed::Box,
+        sync::{
+            Arc,
+            atomic::{AtomicBool, Ordering},
+        },
+    };
+
+    let _fixture = axvisor_api::mock::fixture();
+
+    time::mock::register_timer.set(|(deadline, callback)| {
+        callback(deadline);
+        42
+    });
+
+    let fired = Arc::new(AtomicBool::new(false));
+    let fired_clone = fired.clone();
+    let token = time::register_timer(
+        Duration::from_millis(10),
+        Box::new(move |_| fired_clone.store(true, Ordering::Relaxed)),
+    );
+
+    assert_eq!(token, 42);
+    assert!(fired.load(Ordering::Relaxed));
+    assert_eq!(time::mock::register_timer.call_count(), 1);
+}
@@ -0,0 +1,205 @@
+// Copyright 2025 The Axvisor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Arguments of the [`api_def`](macro@crate::api_def) and
+//! [`api_impl`](macro@crate::api_impl) attributes.
+
+use syn::{
+    Error, Ident, LitInt, LitStr, Result, Token,
+    parse::{Parse, ParseStream},
+};
+
+const KEY_MOCK: &str = "mock";
+const KEY_TRACE: &str = "trace";
+const KEY_STATS: &str = "stats";
+const KEY_VERSION: &str = "version";
+const KEY_DYNAMIC: &str = "dynamic";
+const KEY_INTERCEPT: &str = "intercept";
+const KEY_NAMESPACE: &str = "namespace";
+
+fn duplicate_arg_error(ident: &Ident) -> Error {
+    Error::new_spanned(ident, format!("duplicate argument: {ident}"))
+}
+
+fn unknown_arg_error(ident: &Ident) -> Error {
+    Error::new_spanned(ident, format!("unknown argument: {ident}"))
+}
+
+/// Parse the value of the `version` argument, a positive integer, after the
+/// `=`.
+fn parse_version(input: ParseStream) -> Result<u32> {
+    input.parse::<Token![=]>()?;
+    let version: LitInt = input.parse()?;
+    let value = version.base10_parse::<u32>()?;
+    if value == 0 {
+        return Err(Error::new_spanned(
+            version,
+            "version must be a positive integer",
+        ));
+    }
+    Ok(value)
+}
+
+/// Parse the value of the `namespace` argument, either a string literal or an
+/// identifier, after the `=`.
+fn parse_namespace(input: ParseStream) -> Result<Ident> {
+    input.parse::<Token![=]>()?;
+    if input.peek(LitStr) {
+        let lit: LitStr = input.parse()?;
+        lit.parse::<Ident>()
+            .map_err(|_| Error::new_spanned(&lit, "namespace must be a valid identifier"))
+    } else {
+        input.parse()
+    }
+}
+
+/// Arguments of the `api_def` attribute.
+#[derive(Debug, Default)]
+pub struct ApiDefArgs {
+    /// Generate a mock implementation of the API trait, enabled by the `mock`
+    /// feature of the crate defining the trait.
+    pub mock: bool,
+    /// Report the calls to the API functions to the trace sink, enabled by
+    /// the `trace` feature of the crate defining the trait.
+    pub trace: bool,
+    /// Count the calls to the API functions and measure their latency,
+    /// enabled by the `stats` feature of the crate defining the trait.
+    pub stats: bool,
+    /// The version of the API trait, embedded in the symbols of the API
+    /// functions and in the namespace guard, so that implementations of
+    /// other versions fail to compile.
+    pub version: Option<u32>,
+    /// Allow the implementation of the API trait to be replaced at runtime.
+    pub dynamic: bool,
+    /// Generate interceptors of the API functions, enabled by the
+    /// `intercept` feature of the crate defining the trait.
+    pub intercept: bool,
+    /// The namespace of the symbols of the API functions, instead of the
+    /// default one.
+    pub namespace: Option<Ident>,
+}
+
+impl Parse for ApiDefArgs {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let mut args = ApiDefArgs::default();
+
+        while !input.is_empty() {
+            let ident: Ident = input.parse()?;
+
+            match ident.to_string().as_str() {
+                KEY_MOCK => {
+                    if args.mock {
+                        return Err(duplicate_arg_error(&ident));
+                    }
+                    args.mock = true;
+                }
+                KEY_TRACE => {
+                    if args.trace {
+                        return Err(duplicate_arg_error(&ident));
+                    }
+                    args.trace = true;
+                }
+                KEY_STATS => {
+                    if args.stats {
+                        return Err(duplicate_arg_error(&ident));
+                    }
+                    args.stats = true;
+                }
+                KEY_VERSION => {
+                    if args.version.is_some() {
+                        return Err(duplicate_arg_error(&ident));
+                    }
+                    args.version = Some(parse_version(input)?);
+                }
+                KEY_DYNAMIC => {
+                    if args.dynamic {
+                        return Err(duplicate_arg_error(&ident));
+                    }
+                    args.dynamic = true;
+                }
+                KEY_INTERCEPT => {
+                    if args.intercept {
+                        return Err(duplicate_arg_error(&ident));
+                    }
+                    args.intercept = true;
+                }
+                KEY_NAMESPACE => {
+                    if args.namespace.is_some() {
+                        return Err(duplicate_arg_error(&ident));
+                    }
+                    args.namespace = Some(parse_namespace(input)?);
+                }
+                _ => return Err(unknown_arg_error(&ident)),
+            }
+
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+
+        Ok(args)
+    }
+}
+
+/// Arguments of the `api_impl` attribute.
+#[derive(Debug, Default)]
+pub struct ApiImplArgs {
+    /// Define an implementation to be installed at runtime, instead of
+    /// exporting it at link time.
+    pub dynamic: bool,
+    /// The namespace of the implemented API trait, which must match the one
+    /// given to `api_def`.
+    pub namespace: Option<Ident>,
+    /// The version of the implemented API trait, which must match the one
+    /// given to `api_def`.
+    pub version: Option<u32>,
+}
+
+impl Parse for ApiImplArgs {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let mut args = ApiImplArgs::default();
+
+        while !input.is_empty() {
+            let ident: Ident = input.parse()?;
+
+            match ident.to_string().as_str() {
+                KEY_DYNAMIC => {
+                    if args.dynamic {
+                        return Err(duplicate_arg_error(&ident));
+                    }
+                    args.dynamic = true;
+                }
+                KEY_NAMESPACE => {
+                    if args.namespace.is_some() {
+                        return Err(duplicate_arg_error(&ident));
+                    }
+                    args.namespace = Some(parse_namespace(input)?);
+                }
+                KEY_VERSION => {
+                    if args.version.is_some() {
+                        return Err(duplicate_arg_error(&ident));
+                    }
+                    args.version = Some(parse_version(input)?);
+                }
+                _ => return Err(unknown_arg_error(&ident)),
+            }
+
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+
+        Ok(args)
+    }
+}
@@ -0,0 +1,663 @@
+// Copyright 2025 The Axvisor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Expansion of the [`api_def`](macro@crate::api_def) attribute.
+
+use proc_macro2::{Literal, TokenStream};
+use quote::quote;
+use syn::{
+    Attribute, Error, FnArg, Ident, ItemTrait, Meta, Pat, PatType, ReturnType, Signature,
+    TraitItem, TraitItemFn, Type, parse_quote,
+};
+
+use crate::{args::ApiDefArgs, axvisor_api_crate, axvisor_api_namespace, naming};
+
+/// Collect the names and types of the arguments of an API function.
+fn fn_args(sig: &Signature) -> syn::Result<(Vec<&Ident>, Vec<&Type>)> {
+    let mut names = vec![];
+    let mut types = vec![];
+    for arg in &sig.inputs {
+        match arg {
+            FnArg::Typed(PatType { pat, ty, .. }) => match pat.as_ref() {
+                Pat::Ident(pat_ident) => {
+                    names.push(&pat_ident.ident);
+                    types.push(ty.as_ref());
+                }
+                _ => return Err(Error::new_spanned(pat, "expected an identifier pattern")),
+            },
+            FnArg::Receiver(recv) => {
+                return Err(Error::new_spanned(
+                    recv,
+                    "API functions must not have a receiver",
+                ));
+            }
+        }
+    }
+    Ok((names, types))
+}
+
+/// Get the `#[cfg]` attributes of an API function.
+fn cfg_attrs(attrs: &[Attribute]) -> impl Iterator<Item = &Attribute> {
+    attrs.iter().filter(|attr| attr.path().is_ident("cfg"))
+}
+
+/// Get a `cfg` predicate which holds if any of the API functions exists, or
+/// `None` if some function is unconditional.
+fn any_fn_cfg(fns: &[&TraitItemFn]) -> Option<TokenStream> {
+    let mut preds = vec![];
+    for func in fns {
+        let fn_preds: Vec<_> = cfg_attrs(&func.attrs)
+            .filter_map(|attr| match &attr.meta {
+                Meta::List(list) => Some(&list.tokens),
+                _ => None,
+            })
+            .collect();
+        if fn_preds.is_empty() {
+            return None;
+        }
+        preds.push(quote! { all(#(#fn_preds),*) });
+    }
+    Some(quote! { any(#(#preds),*) })
+}
+
+/// Wrap the call to an API function in a caller with the tracing hooks.
+///
+/// The hooks are only compiled when the `trace` feature of the crate defining
+/// the trait is enabled, see the `trace` module of `axvisor_api` for details.
+fn gen_traced_call(
+    axvisor_api_path: &TokenStream,
+    trait_name: &Ident,
+    fn_name: &Ident,
+    arg_names: &[&Ident],
+    call: TokenStream,
+) -> TokenStream {
+    let trait_name = trait_name.to_string();
+    let fn_name = fn_name.to_string();
+    let arg_strs = arg_names.iter().map(|name| name.to_string());
+
+    quote! {{
+        #[cfg(feature = "trace")]
+        #[allow(unused_imports)]
+        use #axvisor_api_path::trace::{TraceDebug as _, TraceOpaque as _, Traced};
+        #[cfg(feature = "trace")]
+        const __API_FN: #axvisor_api_path::ApiFn =
+            #axvisor_api_path::ApiFn::new(#trait_name, #fn_name);
+        #[cfg(feature = "trace")]
+        #axvisor_api_path::trace::enter(
+            __API_FN,
+            &[#((#arg_strs, (&Traced(&#arg_names)).as_debug())),*],
+        );
+        let __ret = #call;
+        #[cfg(feature = "trace")]
+        #axvisor_api_path::trace::exit(__API_FN, (&Traced(&__ret)).as_debug());
+        __ret
+    }}
+}
+
+/// Wrap the call to an API function in a caller with the statistics
+/// counters.
+///
+/// The counters are only compiled when the `stats` feature of the crate
+/// defining the trait is enabled, see the `stats` module of `axvisor_api` for
+/// details.
+fn gen_counted_call(
+    axvisor_api_path: &TokenStream,
+    trait_name: &Ident,
+    fn_name: &Ident,
+    call: TokenStream,
+) -> TokenStream {
+    let trait_name = trait_name.to_string();
+    let fn_name = fn_name.to_string();
+
+    quote! {{
+        #[cfg(feature = "stats")]
+        static __API_COUNTERS: #axvisor_api_path::stats::ApiFnCounters =
+            #axvisor_api_path::stats::ApiFnCounters::new(
+                #axvisor_api_path::ApiFn::new(#trait_name, #fn_name),
+            );
+        #[cfg(feature = "stats")]
+        let __start = __API_COUNTERS.start();
+        let __ret = #call;
+        #[cfg(feature = "stats")]
+        __API_COUNTERS.record(__start);
+        __ret
+    }}
+}
+
+/// Wrap the call to an API function in a caller with its interceptor.
+///
+/// The interceptor is only compiled when the `intercept` feature of the crate
+/// defining the trait is enabled, see the `intercept` module of `axvisor_api`
+/// for details.
+fn gen_intercepted_call(fn_name: &Ident, arg_names: &[&Ident], call: TokenStream) -> TokenStream {
+    quote! {{
+        #[cfg(feature = "intercept")]
+        let __ret = {
+            let mut __args = (#(#arg_names,)*);
+            match intercept::#fn_name.pre(&mut __args) {
+                Some(__ret) => __ret,
+                None => {
+                    let (#(#arg_names,)*) = __args;
+                    intercept::#fn_name.post(#call)
+                }
+            }
+        };
+        #[cfg(not(feature = "intercept"))]
+        let __ret = #call;
+        __ret
+    }}
+}
+
+/// Generate the `intercept` module of an API trait.
+fn gen_intercept(
+    axvisor_api_path: &TokenStream,
+    item_trait: &ItemTrait,
+    fns: &[&TraitItemFn],
+) -> syn::Result<TokenStream> {
+    let vis = &item_trait.vis;
+    let trait_name = &item_trait.ident;
+
+    let mut statics = vec![];
+    let mut fn_names = vec![];
+    let mut fn_cfgs = vec![];
+
+    for TraitItemFn { attrs, sig, .. } in fns {
+        let fn_name = &sig.ident;
+        let (_, arg_types) = fn_args(sig)?;
+        let cfgs = cfg_attrs(attrs);
+        let cfgs = quote! { #(#cfgs)* };
+        let ret = match &sig.output {
+            ReturnType::Default => quote! { () },
+            ReturnType::Type(_, ty) => quote! { #ty },
+        };
+        let name = format!("{trait_name}::{fn_name}");
+        let doc = format!("Interceptor of [`{fn_name}`](super::{fn_name}).");
+
+        statics.push(quote! {
+            #cfgs
+            #[doc = #doc]
+            #[allow(non_upper_case_globals)]
+            pub static #fn_name: Interceptor<(#(#arg_types,)*), #ret> = Interceptor::new(#name);
+        });
+        fn_names.push(fn_name);
+        fn_cfgs.push(cfgs);
+    }
+
+    let mod_doc = format!("Interceptors of the [`{trait_name}`] API functions.");
+
+    Ok(quote! {
+        #[doc = #mod_doc]
+        ///
+        /// See [`axvisor_api::intercept`] for how to use the interceptors.
+        #[cfg(feature = "intercept")]
+        #vis mod intercept {
+            #[allow(unused_imports)]
+            use super::*;
+            use #axvisor_api_path::intercept::Interceptor;
+
+            #(#statics)*
+
+            /// Remove the hooks of all interceptors of the API trait.
+            pub fn reset() {
+                #(#fn_cfgs #fn_names.reset();)*
+            }
+        }
+    })
+}
+
+/// Generate the `mock` module of an API trait.
+///
+/// The module contains a `MockFn` static for each API function and an
+/// implementation of the trait dispatching to them, see the `mock` module of
+/// `axvisor_api` for details.
+fn gen_mock(
+    axvisor_api_path: &TokenStream,
+    args: &ApiDefArgs,
+    item_trait: &ItemTrait,
+    fns: &[&TraitItemFn],
+) -> syn::Result<TokenStream> {
+    let vis = &item_trait.vis;
+    let trait_name = &item_trait.ident;
+
+    let mut statics = vec![];
+    let mut methods = vec![];
+    let mut fn_names = vec![];
+    let mut fn_cfgs = vec![];
+
+    for TraitItemFn {
+        attrs,
+        sig,
+        default,
+        ..
+    } in fns
+    {
+        let fn_name = &sig.ident;
+        let (arg_names, arg_types) = fn_args(sig)?;
+        let cfgs = cfg_attrs(attrs);
+        let cfgs = quote! { #(#cfgs)* };
+        let ret = match &sig.output {
+            ReturnType::Default => quote! { () },
+            ReturnType::Type(_, ty) => quote! { #ty },
+        };
+        let mock_name = format!("{trait_name}::{fn_name}");
+        let doc = format!("Mock of [`{fn_name}`](super::{fn_name}).");
+        let fallback = match default {
+            Some(default) => quote! { #default },
+            None => quote! { (&Unmocked::<#ret>::new(#mock_name)).value() },
+        };
+
+        statics.push(quote! {
+            #cfgs
+            #[doc = #doc]
+            #[allow(non_upper_case_globals)]
+            pub static #fn_name: MockFn<(#(#arg_types,)*), #ret> = MockFn::new(#mock_name);
+        });
+        methods.push(quote! {
+            #cfgs
+            #[allow(unused_variables)]
+            #sig {
+                let args = (#(#arg_names,)*);
+                let recorded = (&Recorder(&args)).record();
+                self::mock::#fn_name.call(args, recorded, |(#(#arg_names,)*)| #fallback)
+            }
+        });
+        fn_names.push(fn_name);
+        fn_cfgs.push(cfgs);
+    }
+
+    let mod_doc = format!("Mocks of the [`{trait_name}`] API functions.");
+    let namespace = args.namespace.as_ref().map(|ns| quote! { namespace = #ns });
+    let version = args.version.map(|version| {
+        let version = Literal::u32_unsuffixed(version);
+        quote! { version = #version }
+    });
+    let impl_args: Vec<_> = [namespace, version].into_iter().flatten().collect();
+
+    Ok(quote! {
+        #[doc = #mod_doc]
+        ///
+        /// See [`axvisor_api::mock`] for how to use the mocks.
+        #[cfg(feature = "mock")]
+        #vis mod mock {
+            #[allow(unused_imports)]
+            use super::*;
+            use #axvisor_api_path::mock::MockFn;
+
+            #(#statics)*
+
+            /// Reset all mocked functions of the API trait.
+            pub fn reset() {
+                #(#fn_cfgs #fn_names.reset();)*
+            }
+
+            /// Verify the expectations on all mocked functions of the API
+            /// trait.
+            pub fn verify() {
+                #(#fn_cfgs #fn_names.verify();)*
+            }
+
+            #[doc(hidden)]
+            pub struct Mock;
+        }
+
+        // The implementation is placed outside the `mock` module, so default
+        // bodies resolve names the same way as in the trait. It is never
+        // exported, so enabling the `mock` feature does not clash with the
+        // real implementations, even in the tests of the defining crate.
+        #[cfg(feature = "mock")]
+        const _: () = {
+            #[allow(unused_imports)]
+            use #axvisor_api_path::mock::{
+                RecordClone as _, RecordNone as _, Recorder, Unmocked, UnmockedDefault as _,
+                UnmockedPanic as _,
+            };
+            use self::mock::Mock;
+
+            #[#axvisor_api_path::api_impl(dynamic, #(#impl_args),*)]
+            impl #trait_name for Mock {
+                #(#methods)*
+            }
+        };
+    })
+}
+
+/// Wrap the `unsafe fn` items of an API trait in a pass-through macro, so
+/// `crate_interface` leaves them alone.
+///
+/// The callers of these functions link to the symbols `api_impl` exports
+/// itself, see [`implementation::expand`](crate::implementation::expand).
+fn hide_unsafe_fns(axvisor_api_path: &TokenStream, items: &mut [TraitItem]) {
+    for item in items {
+        if let TraitItem::Fn(func) = item
+            && func.sig.unsafety.is_some()
+        {
+            *item = parse_quote! {
+                #axvisor_api_path::__priv::unsafe_api_fn! { #func }
+            };
+        }
+    }
+}
+
+/// Expand `api_def` on a trait.
+///
+/// Functions without a default body are extern symbols exported by the
+/// implementation through `crate_interface`, in the namespace of the trait
+/// version, whose guard constant makes the compiler reject implementations
+/// of another version. Functions with a default body are instead collected into a
+/// table of function pointers, which `api_impl` exports for the implementing
+/// type, so the table holds either the overriding implementation or the
+/// default body.
+///
+/// The function exporting the table also serves as the registration marker
+/// of the implementation, which is referenced by the generated
+/// `assert_implemented` function to check that the trait is implemented.
+///
+/// `unsafe fn` items, which `crate_interface` does not support, are hidden
+/// from it in a pass-through macro, see [`hide_unsafe_fns`].
+pub fn expand(args: ApiDefArgs, mut item_trait: ItemTrait) -> syn::Result<TokenStream> {
+    let axvisor_api_path = axvisor_api_crate();
+    let base_ns = args.namespace.clone().unwrap_or_else(axvisor_api_namespace);
+    let ns = naming::versioned_ns(&base_ns, args.version);
+    let vis = &item_trait.vis;
+    let trait_name = &item_trait.ident;
+    let api_mod = naming::api_mod_name(trait_name);
+    let defaults_const = naming::default_fns_const_name();
+    let impl_symbol = naming::impl_symbol(&ns, trait_name);
+
+    let fns: Vec<_> = item_trait
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            TraitItem::Fn(func) => Some(func),
+            _ => None,
+        })
+        .collect();
+    let fn_names: Vec<_> = fns.iter().map(|func| &func.sig.ident).collect();
+    let check_symbol = naming::check_symbol(&base_ns, trait_name, args.version, &fn_names);
+
+    let mut callers = vec![];
+    let mut untraced_callers = vec![];
+    let mut extern_fns = vec![];
+    let mut table_fields = vec![];
+    let mut table_values = vec![];
+    let mut vtable_fields = vec![];
+    let mut vtable_values = vec![];
+
+    for TraitItemFn {
+        attrs,
+        sig,
+        default,
+        ..
+    } in &fns
+    {
+        let fn_name = &sig.ident;
+        let (arg_names, arg_types) = fn_args(sig)?;
+        let cfgs = cfg_attrs(attrs);
+        let cfgs = quote! { #(#cfgs)* };
+        let unsafety = &sig.unsafety;
+        let output = &sig.output;
+        let call = if default.is_some() {
+            table_fields.push(quote! {
+                #cfgs
+                pub #fn_name: #unsafety fn(#(#arg_types),*) #output,
+            });
+            table_values.push(quote! {
+                #cfgs
+                #fn_name: <Self as #trait_name>::#fn_name,
+            });
+
+            quote! {
+                unsafe { (#api_mod::default_fns().#fn_name)(#(#arg_names),*) }
+            }
+        } else {
+            let fn_symbol = naming::fn_symbol(&ns, trait_name, fn_name);
+            extern_fns.push(quote! {
+                #cfgs
+                #[link_name = #fn_symbol]
+                pub #unsafety fn #fn_name(#(#arg_names: #arg_types),*) #output;
+            });
+
+            quote! {
+                unsafe { #api_mod::extern_fns::#fn_name(#(#arg_names),*) }
+            }
+        };
+
+        let call = if args.dynamic {
+            vtable_fields.push(quote! {
+                #cfgs
+                pub #fn_name: #unsafety fn(#(#arg_types),*) #output,
+            });
+            vtable_values.push(quote! {
+                #cfgs
+                #fn_name: <I as #trait_name>::#fn_name,
+            });
+
+            quote! {
+                match #api_mod::SLOT.get() {
+                    Some(vtable) => unsafe { (vtable.#fn_name)(#(#arg_names),*) },
+                    None => #call,
+                }
+            }
+        } else {
+            call
+        };
+        untraced_callers.push(quote! {
+            #(#attrs)*
+            #[inline]
+            pub #sig {
+                #call
+            }
+        });
+        let call = if args.intercept {
+            gen_intercepted_call(fn_name, &arg_names, call)
+        } else {
+            call
+        };
+        let call = if args.stats {
+            gen_counted_call(&axvisor_api_path, trait_name, fn_name, call)
+        } else {
+            call
+        };
+        let call = if args.trace {
+            gen_traced_call(&axvisor_api_path, trait_name, fn_name, &arg_names, call)
+        } else {
+            call
+        };
+
+        callers.push(quote! {
+            #(#attrs)*
+            #[inline]
+            #vis #sig {
+                #call
+            }
+        });
+    }
+
+    let assert_implemented = if fns.is_empty() {
+        quote! {
+            /// Check that the API trait is implemented, which is trivially
+            /// true as it has no functions.
+            #[inline]
+            pub fn assert_implemented() {}
+        }
+    } else {
+        let cfg = any_fn_cfg(&fns).map(|pred| quote! { #[cfg(#pred)] });
+        let not_cfg = any_fn_cfg(&fns).map(|pred| {
+            quote! {
+                /// Check that the API trait is implemented, which is
+                /// trivially true as none of its functions is enabled.
+                #[cfg(not(#pred))]
+                #[inline]
+                pub fn assert_implemented() {}
+            }
+        });
+
+        quote! {
+            /// Check that the API trait is implemented.
+            ///
+            /// If no implementation is linked into the final binary, linking
+            /// fails with an undefined reference to the registration marker
+            /// from this function, whose symbol names the trait and its
+            /// functions.
+            #cfg
+            #[inline(never)]
+            #[unsafe(export_name = #check_symbol)]
+            pub fn assert_implemented() {
+                unsafe { __impl() };
+            }
+
+            #not_cfg
+        }
+    };
+
+    let dynamic = if args.dynamic {
+        let marker = naming::dyn_marker_name(trait_name);
+        let marker_doc = format!(
+            "Marker of the [`{trait_name}`] API trait, to install its implementation at runtime."
+        );
+
+        Some(quote! {
+            #[doc = #marker_doc]
+            ///
+            /// See [`axvisor_api::dynamic`] for details.
+            #vis enum #marker {}
+
+            impl #axvisor_api_path::dynamic::DynamicApi for #marker {
+                type Vtable = #api_mod::Vtable;
+
+                #[inline]
+                fn slot() -> &'static #axvisor_api_path::dynamic::VtableSlot<#api_mod::Vtable> {
+                    &#api_mod::SLOT
+                }
+            }
+
+            impl #marker {
+                /// Get the vtable of an implementation of the API trait, to
+                /// install it with `axvisor_api::install`.
+                pub const fn vtable<I: #trait_name>() -> &'static #api_mod::Vtable {
+                    const {
+                        &#api_mod::Vtable {
+                            #(#vtable_values)*
+                        }
+                    }
+                }
+            }
+        })
+    } else {
+        None
+    };
+    let dynamic_items = args.dynamic.then(|| {
+        quote! {
+            /// Table of all API functions of an implementation installed at
+            /// runtime.
+            pub struct Vtable {
+                #(#vtable_fields)*
+            }
+
+            /// The installed vtable.
+            pub static SLOT: #axvisor_api_path::dynamic::VtableSlot<Vtable> =
+                #axvisor_api_path::dynamic::VtableSlot::new();
+        }
+    });
+
+    let untraced = (args.trace || args.stats || args.intercept).then(|| {
+        quote! {
+            /// The API functions, bypassing the tracing, the statistics and
+            /// the interceptors of the callers of the parent module.
+            ///
+            /// These are meant for the instrumentation itself, e.g. to read
+            /// the time without measuring it.
+            #vis mod untraced {
+                #[allow(unused_imports)]
+                use super::*;
+
+                #(#untraced_callers)*
+            }
+        }
+    });
+
+    let intercept = if args.intercept {
+        Some(gen_intercept(&axvisor_api_path, &item_trait, &fns)?)
+    } else {
+        None
+    };
+
+    let mock = if args.mock {
+        Some(gen_mock(&axvisor_api_path, &args, &item_trait, &fns)?)
+    } else {
+        None
+    };
+
+    item_trait.items.push(parse_quote! {
+        #[doc(hidden)]
+        const #defaults_const: #api_mod::DefaultFns = #api_mod::DefaultFns {
+            #(#table_values)*
+        };
+    });
+
+    hide_unsafe_fns(&axvisor_api_path, &mut item_trait.items);
+
+    Ok(quote! {
+        #[#axvisor_api_path::__priv::crate_interface::def_interface(namespace = #ns)]
+        #item_trait
+
+        #[doc(hidden)]
+        #[allow(non_snake_case)]
+        #vis mod #api_mod {
+            use super::*;
+
+            /// Table of the API functions with a default body.
+            pub struct DefaultFns {
+                #(#table_fields)*
+            }
+
+            /// The API functions without a default body, exported by the
+            /// implementation.
+            pub mod extern_fns {
+                #[allow(unused_imports)]
+                use super::super::*;
+
+                unsafe extern "Rust" {
+                    #(#extern_fns)*
+                }
+            }
+
+            unsafe extern "Rust" {
+                /// The registration marker exported by the implementation.
+                #[link_name = #impl_symbol]
+                fn __impl() -> *const DefaultFns;
+            }
+
+            /// Get the default function table of the implementation.
+            #[inline]
+            pub fn default_fns() -> &'static DefaultFns {
+                unsafe { &*__impl() }
+            }
+
+            #assert_implemented
+
+            #dynamic_items
+        }
+
+        #(#callers)*
+
+        #untraced
+
+        #dynamic
+
+        #intercept
+
+        #mock
+    })
+}
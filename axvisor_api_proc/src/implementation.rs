@@ -0,0 +1,162 @@
+// Copyright 2025 The Axvisor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Expansion of the [`api_impl`](macro@crate::api_impl) attribute.
+
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{Error, FnArg, Ident, ImplItem, ImplItemFn, ItemImpl, Path, Type, parse_quote};
+
+use crate::{args::ApiImplArgs, axvisor_api_crate, axvisor_api_namespace, naming};
+
+/// Expand `api_impl` on an impl block.
+///
+/// Besides the symbols exported by `crate_interface`, the registration marker
+/// of the implementation is exported, which returns the default function
+/// table of the implementing type. See [`definition::expand`](crate::definition::expand).
+///
+/// The symbols are exported in the namespace of the version given with the
+/// `version` argument, whose guard constant the trait only accepts if it has
+/// the same version, so mismatched implementations fail to compile.
+///
+/// `unsafe fn` items, which `crate_interface` does not support, are hidden
+/// from it in a pass-through macro, and exported by this macro instead.
+pub fn expand(args: ApiImplArgs, item_impl: ItemImpl) -> syn::Result<TokenStream> {
+    let base_ns = args.namespace.unwrap_or_else(axvisor_api_namespace);
+    let ns = naming::versioned_ns(&base_ns, args.version);
+    if args.dynamic {
+        return expand_dynamic(ns, item_impl);
+    }
+
+    let axvisor_api_path = axvisor_api_crate();
+    let Some((_, trait_path, _)) = &item_impl.trait_ else {
+        return Err(Error::new_spanned(
+            &item_impl,
+            "expected a trait implementation",
+        ));
+    };
+    let trait_name = &trait_path.segments.last().unwrap().ident;
+    let self_ty = &item_impl.self_ty;
+    let defaults_const = naming::default_fns_const_name();
+    let impl_symbol = naming::impl_symbol(&ns, trait_name);
+
+    let mut exports = vec![];
+    for item in &item_impl.items {
+        if let ImplItem::Fn(func) = item
+            && func.sig.unsafety.is_some()
+        {
+            let cfgs = func.attrs.iter().filter(|attr| attr.path().is_ident("cfg"));
+            let cfgs = quote! { #(#cfgs)* };
+            let fn_symbol = naming::fn_symbol(&ns, trait_name, &func.sig.ident);
+            exports.push(gen_unsafe_export(
+                &cfgs, &fn_symbol, self_ty, trait_path, func,
+            )?);
+        }
+    }
+
+    let hidden_impl = hide_unsafe_fns(&axvisor_api_path, &item_impl);
+
+    Ok(quote! {
+        #[#axvisor_api_path::__priv::crate_interface::impl_interface(namespace = #ns)]
+        #hidden_impl
+
+        const _: () = {
+            #(#exports)*
+
+            #[unsafe(export_name = #impl_symbol)]
+            extern "Rust" fn __impl() -> *const () {
+                let default_fns = const { &<#self_ty as #trait_path>::#defaults_const };
+                default_fns as *const _ as *const ()
+            }
+        };
+    })
+}
+
+/// Generate the exported function calling an `unsafe fn` of the
+/// implementation, in place of `crate_interface`.
+fn gen_unsafe_export(
+    cfgs: &TokenStream,
+    fn_symbol: &str,
+    self_ty: &Type,
+    trait_path: &Path,
+    func: &ImplItemFn,
+) -> syn::Result<TokenStream> {
+    let fn_name = &func.sig.ident;
+    let output = &func.sig.output;
+    let mut arg_names = vec![];
+    let mut arg_types = vec![];
+    for (i, arg) in func.sig.inputs.iter().enumerate() {
+        match arg {
+            FnArg::Typed(pat_type) => {
+                arg_names.push(format_ident!("__arg{}", i));
+                arg_types.push(&pat_type.ty);
+            }
+            FnArg::Receiver(recv) => {
+                return Err(Error::new_spanned(
+                    recv,
+                    "API functions must not have a receiver",
+                ));
+            }
+        }
+    }
+
+    Ok(quote! {
+        #cfgs
+        #[unsafe(export_name = #fn_symbol)]
+        unsafe extern "Rust" fn #fn_name(#(#arg_names: #arg_types),*) #output {
+            unsafe { <#self_ty as #trait_path>::#fn_name(#(#arg_names),*) }
+        }
+    })
+}
+
+/// Get a copy of an implementation with its `unsafe fn` items wrapped in a
+/// pass-through macro, so `crate_interface` leaves them alone.
+fn hide_unsafe_fns(axvisor_api_path: &TokenStream, item_impl: &ItemImpl) -> ItemImpl {
+    let mut item_impl = item_impl.clone();
+    for item in &mut item_impl.items {
+        if let ImplItem::Fn(func) = item
+            && func.sig.unsafety.is_some()
+        {
+            *item = parse_quote! {
+                #axvisor_api_path::__priv::unsafe_api_fn! { #func }
+            };
+        }
+    }
+    item_impl
+}
+
+/// Expand `api_impl(dynamic)` on an impl block.
+///
+/// Nothing is exported, the implementation is only added the associated
+/// constants `crate_interface` requires, so it can be installed at runtime.
+fn expand_dynamic(ns: Ident, mut item_impl: ItemImpl) -> syn::Result<TokenStream> {
+    let Some((_, trait_path, _)) = &item_impl.trait_ else {
+        return Err(Error::new_spanned(
+            &item_impl,
+            "expected a trait implementation",
+        ));
+    };
+    let trait_name = &trait_path.segments.last().unwrap().ident;
+    let alias_guard = naming::alias_guard_name(trait_name);
+    let ns_guard = naming::namespace_guard_name(&ns);
+
+    item_impl.items.push(parse_quote! {
+        const #alias_guard: () = ();
+    });
+    item_impl.items.push(parse_quote! {
+        const #ns_guard: () = ();
+    });
+
+    Ok(quote! { #item_impl })
+}
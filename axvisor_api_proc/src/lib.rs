@@ -23,6 +23,7 @@
 //!
 //! - [`api_def`] - Define an API interface trait.
 //! - [`api_impl`] - Implement an API interface.
+//! - [`assert_api_implemented!`] - Check that an API interface is implemented.
 //!
 //! # Usage
 //!
@@ -54,11 +55,18 @@
 //! implementations. This allows for a cleaner API without explicit generic
 //! parameters.
 
+mod args;
+mod definition;
+mod implementation;
+mod naming;
+
 use proc_macro::TokenStream as TokenStream1;
 use proc_macro_crate::{FoundCrate, crate_name};
 use proc_macro2::{Span, TokenStream};
-use quote::{quote, quote_spanned};
-use syn::{Ident, spanned::Spanned};
+use quote::quote;
+use syn::{Error, Ident, ItemImpl, ItemTrait, Path};
+
+use crate::args::{ApiDefArgs, ApiImplArgs};
 
 /// Find the path to the `axvisor_api` crate.
 ///
@@ -77,27 +85,16 @@ fn axvisor_api_crate() -> TokenStream {
     }
 }
 
-/// Get the namespace identifier used for AxVisor APIs.
+/// Get the default namespace identifier used for AxVisor APIs.
 ///
 /// All AxVisor APIs share a common namespace to avoid conflicts with other
-/// uses of `crate_interface`.
+/// uses of `crate_interface`, unless another one is given with the
+/// `namespace` argument.
 fn axvisor_api_namespace() -> Ident {
     const AXVISOR_API_NS: &str = "AxVisorApi";
     Ident::new(AXVISOR_API_NS, Span::call_site())
 }
 
-/// Macro to assert that an attribute has no arguments.
-macro_rules! assert_empty_attr {
-    ($attr:expr) => {
-        if !$attr.is_empty() {
-            return (quote_spanned! {
-                TokenStream::from($attr).span() => compile_error!("This attribute does not accept any arguments")
-            })
-            .into();
-        }
-    };
-}
-
 /// Define an AxVisor API interface.
 ///
 /// This attribute macro is applied to a trait definition to register it as
@@ -130,28 +127,98 @@ macro_rules! assert_empty_attr {
 ///    attribute.
 /// 2. Free-standing caller functions for each trait method at the same
 ///    module level.
+/// 3. A hidden module next to the trait, holding the table type used to
+///    dispatch the trait methods with a default body, and the check function
+///    used by [`assert_api_implemented!`].
+///
+/// # Default Implementations
+///
+/// Trait methods may have a default body, which is used as a fallback if the
+/// [`api_impl`] block does not implement the method. This allows new API
+/// functions to be added to an existing trait without breaking existing
+/// implementations.
+///
+/// ```rust,ignore
+/// #[api_def]
+/// pub trait MyApiIf {
+///     fn get_value() -> u32;
+///
+///     /// Get the value plus one. Implementations may override it.
+///     fn get_value_plus_one() -> u32 {
+///         get_value() + 1
+///     }
+/// }
+/// ```
 ///
 /// # Attributes
 ///
-/// This macro does not accept any arguments.
+/// - `mock`: Also generate a `mock` module next to the trait, containing a
+///   settable and recordable `axvisor_api::mock::MockFn` for each method and a
+///   mock implementation of the trait dispatching to them. The module is only
+///   compiled when the `mock` feature of the crate defining the trait is
+///   enabled, so that crate must declare a `mock` feature. The mock
+///   implementation is never exported, so it does not clash with the real
+///   one. Tests export it with forwarding implementations, like
+///   `axvisor_api_mock` does.
+/// - `trace`: Report every call to the API functions, with the arguments and
+///   the return value, to the sink installed with `axvisor_api::trace`. The
+///   tracing hooks are only compiled when the `trace` feature of the crate
+///   defining the trait is enabled, so that crate must declare a `trace`
+///   feature.
+/// - `stats`: Count the calls to every API function and measure their
+///   latency, readable with `axvisor_api::stats::snapshot`. The counters are
+///   only compiled when the `stats` feature of the crate defining the trait is
+///   enabled, so that crate must declare a `stats` feature.
+/// - `version = N`: Set the version of the trait, a positive integer, which
+///   should be increased whenever the signatures of the trait change. The
+///   version is embedded in the symbols of the API functions, like
+///   `__AxVisorApi_v2_VmmIf_inject_interrupt`, and implementations must be
+///   given the same version with `#[api_impl(version = N)]`, so an
+///   implementation written against another version of the trait fails to
+///   compile, with an error about the namespace guard constant
+///   `__NamespaceGuard__AxVisorApi_v2`. If no implementation is linked, a
+///   failing [`assert_api_implemented!`] reports
+///   `AxVisorApi::VmmIf requires an #[api_impl] built against version 2`.
+/// - `dynamic`: Allow the implementation to be replaced at runtime. A marker
+///   type `Dyn{Trait}` is generated next to the trait, for which
+///   implementations defined with `#[api_impl(dynamic)]` can be installed by
+///   `axvisor_api::install`. The callers dispatch to the installed
+///   implementation through an atomically replaced vtable, or to the
+///   link-time implementation if none is installed.
+/// - `intercept`: Also generate an `intercept` module next to the trait,
+///   containing an `axvisor_api::intercept::Interceptor` for each method,
+///   whose hooks are called by the callers around the implementation. The
+///   module and the hooks are only compiled when the `intercept` feature of
+///   the crate defining the trait is enabled, so that crate must declare an
+///   `intercept` feature.
+///
+///   With `trace`, `stats` or `intercept`, an `untraced` module is also
+///   generated next to the trait, containing callers of the API functions
+///   which bypass the tracing, the counters and the interceptors, for use by
+///   the instrumentation itself.
+/// - `namespace = "Name"`: Use `Name` instead of `AxVisorApi` as the
+///   namespace of the symbols of the API functions, so independent API
+///   families with the same trait names do not collide. Implementations must
+///   be given the same namespace.
 ///
 /// # Implementation
 ///
-/// This macro uses `crate_interface::def_interface` internally with the
-/// `gen_caller` option to generate the caller functions.
+/// This macro uses `crate_interface::def_interface` internally. Methods
+/// without a default body are called through the extern symbols exported by
+/// `crate_interface`, while methods with a default body are called through a
+/// table of function pointers exported by [`api_impl`].
+///
+/// Methods may be `unsafe fn`, in which case the callers are `unsafe fn`
+/// too, with the same safety requirements. As `crate_interface` does not
+/// support them, they are hidden from it, and [`api_impl`] exports them
+/// itself under the same symbols.
 #[proc_macro_attribute]
 pub fn api_def(attr: TokenStream1, input: TokenStream1) -> TokenStream1 {
-    assert_empty_attr!(attr);
-
-    let axvisor_api_path = axvisor_api_crate();
-    let ns = axvisor_api_namespace();
-    let input: TokenStream = syn::parse_macro_input!(input as TokenStream);
-
-    quote! {
-        #[#axvisor_api_path::__priv::crate_interface::def_interface(gen_caller, namespace = #ns)]
-        #input
-    }
-    .into()
+    let args = syn::parse_macro_input!(attr as ApiDefArgs);
+    let input = syn::parse_macro_input!(input as ItemTrait);
+    definition::expand(args, input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
 }
 
 /// Implement an AxVisor API interface.
@@ -189,22 +256,69 @@ pub fn api_def(attr: TokenStream1, input: TokenStream1) -> TokenStream1 {
 ///
 /// # Attributes
 ///
-/// This macro does not accept any arguments.
+/// - `dynamic`: Define an implementation of a trait defined with
+///   `#[api_def(dynamic)]` to be installed at runtime, instead of the
+///   link-time implementation. Nothing is exported, so any number of such
+///   implementations may exist in the final binary.
+/// - `namespace = "Name"`: Implement a trait defined with the same namespace
+///   in [`api_def`].
+/// - `version = N`: Implement a trait defined with the same version in
+///   [`api_def`].
 ///
 /// # Implementation
 ///
-/// This macro uses `crate_interface::impl_interface` internally.
+/// This macro uses `crate_interface::impl_interface` internally, and
+/// additionally exports a registration marker for the trait, which provides
+/// the table of the trait methods with a default body, pointing to either the
+/// overriding implementation or the default one. If the trait has a version,
+/// all symbols are exported in the namespace of that version.
 #[proc_macro_attribute]
 pub fn api_impl(attr: TokenStream1, input: TokenStream1) -> TokenStream1 {
-    assert_empty_attr!(attr);
+    let args = syn::parse_macro_input!(attr as ApiImplArgs);
+    let input = syn::parse_macro_input!(input as ItemImpl);
+    implementation::expand(args, input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
 
-    let axvisor_api_path = axvisor_api_crate();
-    let ns = axvisor_api_namespace();
-    let input: TokenStream = syn::parse_macro_input!(input as TokenStream);
+/// Expand to the given items unchanged.
+///
+/// Used to hide `unsafe fn` items from `crate_interface`, which passes item
+/// macros through untouched.
+#[doc(hidden)]
+#[proc_macro]
+pub fn unsafe_api_fn(input: TokenStream1) -> TokenStream1 {
+    input
+}
 
-    quote! {
-        #[#axvisor_api_path::__priv::crate_interface::impl_interface(namespace = #ns)]
-        #input
-    }
-    .into()
+/// Check that an AxVisor API interface is implemented in the final binary.
+///
+/// This macro takes the path to a trait defined with [`api_def`] and expands
+/// to a call to the check function generated for it. The path must be
+/// written in full, not through a `use` alias, as the check function is
+/// located in a hidden module next to the trait.
+///
+/// If no [`api_impl`] block for the trait is linked into the final binary,
+/// linking fails with a single undefined reference to the registration
+/// marker of the trait, reported in a function whose symbol names the trait
+/// and all its functions, for example:
+///
+/// ```text
+/// undefined symbol: __AxVisorApi_TimeIf__impl
+/// >>> referenced by ...:(AxVisorApi::TimeIf requires an #[api_impl] (current_ticks, ...))
+/// ```
+///
+/// Use `axvisor_api::assert_all_apis_implemented!` instead of this macro
+/// directly.
+#[proc_macro]
+pub fn assert_api_implemented(input: TokenStream1) -> TokenStream1 {
+    let mut path = syn::parse_macro_input!(input as Path);
+    let Some(last) = path.segments.last_mut() else {
+        return Error::new_spanned(path, "expected a path to an API trait")
+            .into_compile_error()
+            .into();
+    };
+    last.ident = naming::api_mod_name(&last.ident);
+
+    quote! { #path::assert_implemented() }.into()
 }
@@ -0,0 +1,107 @@
+// Copyright 2025 The Axvisor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Names of the items and symbols generated by the macros.
+//!
+//! Names shared by [`api_def`](macro@crate::api_def) and
+//! [`api_impl`](macro@crate::api_impl) are defined here so that both sides
+//! always agree on them.
+
+use core::fmt::Display;
+
+use proc_macro2::Span;
+use quote::format_ident;
+use syn::Ident;
+
+/// Get the namespace of the symbols of an API trait with the given version.
+///
+/// Unversioned traits use the namespace as is.
+pub fn versioned_ns(ns: &Ident, version: Option<u32>) -> Ident {
+    match version {
+        Some(version) => format_ident!("{}_v{}", ns, version),
+        None => ns.clone(),
+    }
+}
+
+/// Get the name of the symbol of an API function, as defined by
+/// `crate_interface`.
+pub fn fn_symbol(ns: impl Display, trait_name: &Ident, fn_name: &Ident) -> String {
+    format!("__{ns}_{trait_name}_{fn_name}")
+}
+
+/// Get the name of the registration marker symbol exported by the
+/// implementation of an API trait.
+///
+/// The marker is a function returning the default function table of the
+/// implementation.
+pub fn impl_symbol(ns: impl Display, trait_name: &Ident) -> String {
+    format!("__{ns}_{trait_name}__impl")
+}
+
+/// Get the name of the symbol of the function checking that an API trait is
+/// implemented.
+///
+/// The symbol is shown by the linker when the check fails, so it is made
+/// human-readable and lists all functions of the trait.
+pub fn check_symbol(
+    ns: &Ident,
+    trait_name: &Ident,
+    version: Option<u32>,
+    fn_names: &[&Ident],
+) -> String {
+    let fn_names = fn_names
+        .iter()
+        .map(|name| name.to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    match version {
+        Some(version) => format!(
+            "{ns}::{trait_name} requires an #[api_impl] built against version {version} ({fn_names})"
+        ),
+        None => format!("{ns}::{trait_name} requires an #[api_impl] ({fn_names})"),
+    }
+}
+
+/// Get the name of the hidden module generated next to an API trait.
+pub fn api_mod_name(trait_name: &Ident) -> Ident {
+    format_ident!("__{}_api", trait_name)
+}
+
+/// Get the name of the hidden associated constant added to an API trait,
+/// which holds the default function table of an implementation.
+pub fn default_fns_const_name() -> Ident {
+    Ident::new("__AXVISOR_API_DEFAULT_FNS", Span::call_site())
+}
+
+/// Get the name of the marker type of a dynamic API trait.
+pub fn dyn_marker_name(trait_name: &Ident) -> Ident {
+    format_ident!("Dyn{}", trait_name)
+}
+
+/// Get the name of the associated constant added by `crate_interface` to
+/// guard against implementing an API trait through an alias.
+///
+/// This is a private name of `crate_interface`, whose version is pinned in
+/// the manifest of `axvisor_api` for this reason.
+pub fn alias_guard_name(trait_name: &Ident) -> Ident {
+    format_ident!("__MustNotAnAlias__{}", trait_name)
+}
+
+/// Get the name of the associated constant added by `crate_interface` to
+/// enforce the namespace of an implementation.
+///
+/// Like [`alias_guard_name`], this is a private name of `crate_interface`.
+pub fn namespace_guard_name(ns: &Ident) -> Ident {
+    format_ident!("__NamespaceGuard__{}", ns)
+}
@@ -50,7 +50,7 @@ use super::{memory::PhysAddr, vmm::InterruptVector};
 /// This trait defines the interface for architecture-specific operations
 /// required by the hypervisor. Implementations should be provided by the
 /// host system or HAL layer.
-#[crate::api_def]
+#[crate::api_def(mock, trace, stats, intercept)]
 pub trait ArchIf {
     /// Inject a virtual interrupt to the current virtual CPU using hardware
     /// virtualization support.
@@ -0,0 +1,134 @@
+// Copyright 2025 The Axvisor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Runtime-switchable implementations of the AxVisor APIs.
+//!
+//! API traits defined with `#[api_def(dynamic)]` can have their link-time
+//! implementation replaced at runtime by another one, for example to swap a
+//! real clock for a virtual one in simulations. For such a trait `FooIf`, a
+//! marker type `DynFooIf` implementing [`DynamicApi`] is generated next to
+//! the trait, and the callers dispatch to the vtable installed for it, or to
+//! the link-time implementation if none is installed.
+//!
+//! Alternative implementations are defined with `#[api_impl(dynamic)]`,
+//! which does not export them at link time, and installed by their vtable:
+//!
+//! ```rust,ignore
+//! use axvisor_api::{api_def, api_impl};
+//!
+//! #[api_def(dynamic)]
+//! pub trait ClockIf {
+//!     fn now() -> u64;
+//! }
+//!
+//! struct RealClock;
+//!
+//! // The default implementation, resolved at link time.
+//! #[api_impl]
+//! impl ClockIf for RealClock {
+//!     fn now() -> u64 { read_counter() }
+//! }
+//!
+//! struct VirtualClock;
+//!
+//! #[api_impl(dynamic)]
+//! impl ClockIf for VirtualClock {
+//!     fn now() -> u64 { VIRTUAL_TICKS.load(Ordering::Relaxed) }
+//! }
+//!
+//! axvisor_api::install::<DynClockIf>(DynClockIf::vtable::<VirtualClock>());
+//! assert_eq!(now(), VIRTUAL_TICKS.load(Ordering::Relaxed));
+//! axvisor_api::uninstall::<DynClockIf>();
+//! ```
+//!
+//! Installing and uninstalling are atomic, so they can be done while the API
+//! is being called, and each call uses either the old or the new
+//! implementation entirely.
+
+use core::{
+    ptr,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+/// An API trait whose implementation can be replaced at runtime.
+///
+/// This trait is implemented by the `Dyn*` marker types generated by
+/// `#[api_def(dynamic)]`, and should not be implemented manually.
+pub trait DynamicApi {
+    /// The table of the API functions.
+    type Vtable: 'static;
+
+    /// Get the slot holding the installed vtable.
+    #[doc(hidden)]
+    fn slot() -> &'static VtableSlot<Self::Vtable>;
+}
+
+/// A slot holding the installed vtable of a dynamic API trait.
+#[doc(hidden)]
+pub struct VtableSlot<V> {
+    vtable: AtomicPtr<V>,
+}
+
+impl<V: 'static> VtableSlot<V> {
+    /// Create an empty slot.
+    pub const fn new() -> Self {
+        Self {
+            vtable: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    /// Get the installed vtable, if any.
+    #[inline]
+    pub fn get(&self) -> Option<&'static V> {
+        // SAFETY: the slot only holds `'static` vtables.
+        unsafe { self.vtable.load(Ordering::Acquire).as_ref() }
+    }
+
+    fn replace(&self, vtable: *const V) -> Option<&'static V> {
+        // SAFETY: the slot only holds `'static` vtables.
+        unsafe {
+            self.vtable
+                .swap(vtable as *mut V, Ordering::AcqRel)
+                .as_ref()
+        }
+    }
+}
+
+impl<V: 'static> Default for VtableSlot<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Install the implementation of the dynamic API trait `A`, given by its
+/// vtable, replacing the currently installed one.
+///
+/// Returns the previously installed vtable, if any.
+pub fn install<A: DynamicApi + ?Sized>(vtable: &'static A::Vtable) -> Option<&'static A::Vtable> {
+    A::slot().replace(vtable)
+}
+
+/// Uninstall the installed implementation of the dynamic API trait `A`, so
+/// the link-time implementation is used again.
+///
+/// Returns the previously installed vtable, if any.
+pub fn uninstall<A: DynamicApi + ?Sized>() -> Option<&'static A::Vtable> {
+    A::slot().replace(ptr::null())
+}
+
+/// Get the installed vtable of the dynamic API trait `A`, or `None` if the
+/// link-time implementation is used.
+pub fn installed<A: DynamicApi + ?Sized>() -> Option<&'static A::Vtable> {
+    A::slot().get()
+}
@@ -25,6 +25,10 @@
 //! # Available APIs
 //!
 //! - [`get_host_cpu_num`] - Get the total number of CPUs in the host system.
+//! - [`get_host_numa_node_num`] - Get the number of NUMA nodes in the host
+//!   system.
+//! - [`get_host_cpu_numa_node`] - Get the NUMA node of a host CPU.
+//! - [`get_host_numa_node_free_memory`] - Get the free memory of a NUMA node.
 //!
 //! # Implementation
 //!
@@ -43,11 +47,16 @@
 //! }
 //! ```
 
+/// NUMA node ID type.
+///
+/// Nodes are numbered from 0 to [`get_host_numa_node_num`] (exclusive).
+pub type NumaNodeId = usize;
+
 /// The API trait for host system functionalities.
 ///
 /// This trait defines the interface for querying host system information.
 /// Implementations should be provided by the host system or HAL layer.
-#[crate::api_def]
+#[crate::api_def(mock, trace, stats, intercept)]
 pub trait HostIf {
     /// Get the total number of CPUs (logical processors) in the host system.
     ///
@@ -66,4 +75,49 @@ pub trait HostIf {
     /// println!("Host has {} CPUs", cpu_count);
     /// ```
     fn get_host_cpu_num() -> usize;
+
+    /// Get the number of NUMA nodes in the host system.
+    ///
+    /// The default implementation reports a single node, for hosts without
+    /// NUMA support.
+    ///
+    /// # Returns
+    ///
+    /// The number of NUMA nodes in the host system.
+    fn get_host_numa_node_num() -> usize {
+        1
+    }
+
+    /// Get the NUMA node a host CPU belongs to.
+    ///
+    /// The default implementation reports node 0 for every CPU.
+    ///
+    /// # Arguments
+    ///
+    /// * `cpu_id` - The ID of the host CPU.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the NUMA node of the CPU.
+    fn get_host_cpu_numa_node(cpu_id: usize) -> NumaNodeId {
+        let _ = cpu_id;
+        0
+    }
+
+    /// Get the amount of free memory of a NUMA node.
+    ///
+    /// The default implementation reports the amount as unknown.
+    ///
+    /// # Arguments
+    ///
+    /// * `node` - The ID of the NUMA node.
+    ///
+    /// # Returns
+    ///
+    /// - `Some(usize)` - The free memory of the node in bytes.
+    /// - `None` - If the node does not exist or the amount is unknown.
+    fn get_host_numa_node_free_memory(node: NumaNodeId) -> Option<usize> {
+        let _ = node;
+        None
+    }
 }
@@ -0,0 +1,222 @@
+// Copyright 2025 The Axvisor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Interception of and fault injection into the AxVisor API calls.
+//!
+//! This module is only available with the `intercept` feature. With it, an
+//! `intercept` module is generated next to every API trait defined with
+//! `#[api_def(intercept)]`, which contains an [`Interceptor`] static for every
+//! API function, with the same name as the function. All API traits of this
+//! crate can be intercepted.
+//!
+//! An interceptor can have a pre-hook, called with the arguments before the
+//! implementation, which may modify them or override the return value, and a
+//! post-hook, called with the return value of the implementation, which may
+//! replace it:
+//!
+//! ```rust,ignore
+//! use axvisor_api::{intercept::FaultPolicy, memory, time};
+//!
+//! // Make every third frame allocation fail.
+//! memory::intercept::alloc_frame.inject_fault(FaultPolicy::every_nth(3), || None);
+//!
+//! // Make timers fire 10ms late.
+//! time::intercept::register_timer.set_pre(|(deadline, _)| {
+//!     *deadline += Duration::from_millis(10);
+//!     None
+//! });
+//!
+//! // ... code under test
+//!
+//! memory::intercept::reset();
+//! time::intercept::reset();
+//! ```
+//!
+//! Interceptors are global, so tests using them should not run concurrently
+//! with other tests calling the intercepted APIs.
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+
+use crate::sync::SpinLock;
+
+/// A pre-hook, see [`Interceptor::set_pre`].
+type PreHook<A, R> = Arc<dyn Fn(&mut A) -> Option<R> + Send + Sync>;
+
+/// A post-hook, see [`Interceptor::set_post`].
+type PostHook<R> = Arc<dyn Fn(R) -> R + Send + Sync>;
+
+/// The hooks of an interceptor.
+struct Hooks<A, R> {
+    pre: Option<PreHook<A, R>>,
+    post: Option<PostHook<R>>,
+}
+
+/// An interceptor of an API function.
+///
+/// `A` is the tuple of the argument types of the function, and `R` is its
+/// return type. See the [module-level documentation](self) for details.
+pub struct Interceptor<A, R> {
+    name: &'static str,
+    hooks: SpinLock<Hooks<A, R>>,
+}
+
+impl<A, R> Interceptor<A, R> {
+    /// Create an interceptor without hooks for the API function with the
+    /// given name.
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            hooks: SpinLock::new(Hooks {
+                pre: None,
+                post: None,
+            }),
+        }
+    }
+
+    /// Get the name of the intercepted function.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Set the pre-hook, replacing the previous one.
+    ///
+    /// The hook is called with the arguments of every call, packed into a
+    /// tuple, before the implementation. It may modify the arguments, and
+    /// return `Some` to skip the implementation and the post-hook, overriding
+    /// the return value.
+    ///
+    /// Hooks are called without any lock held, so concurrent and recursive
+    /// calls of the intercepted function all see the hook.
+    pub fn set_pre(&self, hook: impl Fn(&mut A) -> Option<R> + Send + Sync + 'static) {
+        self.hooks.lock().pre = Some(Arc::new(hook));
+    }
+
+    /// Set the post-hook, replacing the previous one.
+    ///
+    /// The hook is called with the return value of the implementation, and
+    /// returns the value actually returned to the caller.
+    pub fn set_post(&self, hook: impl Fn(R) -> R + Send + Sync + 'static) {
+        self.hooks.lock().post = Some(Arc::new(hook));
+    }
+
+    /// Inject faults into the calls according to `policy`, by setting a
+    /// pre-hook returning the value produced by `fault` for failing calls.
+    pub fn inject_fault(&self, policy: FaultPolicy, fault: impl Fn() -> R + Send + Sync + 'static) {
+        let policy = SpinLock::new(policy);
+        self.set_pre(move |_| policy.lock().should_fail().then(&fault));
+    }
+
+    /// Remove the hooks.
+    pub fn reset(&self) {
+        let mut hooks = self.hooks.lock();
+        hooks.pre = None;
+        hooks.post = None;
+    }
+
+    /// Call the pre-hook, if any.
+    #[doc(hidden)]
+    pub fn pre(&self, args: &mut A) -> Option<R> {
+        // Do not hold the lock while calling the hook, which may call the
+        // intercepted function recursively.
+        let hook = self.hooks.lock().pre.clone()?;
+        hook(args)
+    }
+
+    /// Call the post-hook, if any.
+    #[doc(hidden)]
+    pub fn post(&self, ret: R) -> R {
+        match self.hooks.lock().post.clone() {
+            Some(hook) => hook(ret),
+            None => ret,
+        }
+    }
+}
+
+/// When a fault is injected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FaultKind {
+    EveryNth(u64),
+    AfterBudget(u64),
+    Random { state: u64, one_in: u64 },
+}
+
+/// A policy deciding which calls fail, used by
+/// [`Interceptor::inject_fault`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FaultPolicy {
+    kind: FaultKind,
+    calls: u64,
+}
+
+impl FaultPolicy {
+    const fn new(kind: FaultKind) -> Self {
+        Self { kind, calls: 0 }
+    }
+
+    /// Fail every `n`th call, that is the `n`th, the `2n`th, and so on.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is zero.
+    pub const fn every_nth(n: u64) -> Self {
+        assert!(n > 0, "`n` must be positive");
+        Self::new(FaultKind::EveryNth(n))
+    }
+
+    /// Let the first `budget` calls succeed, and fail all later calls.
+    pub const fn after_budget(budget: u64) -> Self {
+        Self::new(FaultKind::AfterBudget(budget))
+    }
+
+    /// Fail each call with a probability of `1 / one_in`, using a
+    /// pseudo-random generator initialized with `seed`, so the same seed
+    /// always fails the same calls.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `one_in` is zero.
+    pub const fn random(seed: u64, one_in: u64) -> Self {
+        assert!(one_in > 0, "`one_in` must be positive");
+        // xorshift does not leave the zero state
+        let state = if seed == 0 {
+            0x9e37_79b9_7f4a_7c15
+        } else {
+            seed
+        };
+        Self::new(FaultKind::Random { state, one_in })
+    }
+
+    /// Get the number of calls decided so far.
+    pub fn calls(&self) -> u64 {
+        self.calls
+    }
+
+    /// Decide whether the next call fails.
+    pub fn should_fail(&mut self) -> bool {
+        self.calls += 1;
+        match &mut self.kind {
+            FaultKind::EveryNth(n) => self.calls % *n == 0,
+            FaultKind::AfterBudget(budget) => self.calls > *budget,
+            FaultKind::Random { state, one_in } => {
+                // xorshift64*
+                *state ^= *state >> 12;
+                *state ^= *state << 25;
+                *state ^= *state >> 27;
+                state.wrapping_mul(0x2545_f491_4f6c_dd1d) % *one_in == 0
+            }
+        }
+    }
+}
@@ -0,0 +1,283 @@
+// Copyright 2025 The Axvisor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! DMA mapping and IOMMU APIs for the AxVisor hypervisor.
+//!
+//! This module provides APIs to program the host IOMMU (e.g. an ARM SMMU,
+//! Intel VT-d or a RISC-V IOMMU), so devices passed through to guests see
+//! guest physical addresses in their DMA requests.
+//!
+//! # Overview
+//!
+//! The IOMMU APIs provide:
+//! - Creation and destruction of translation domains, usually one per VM
+//! - Mapping and unmapping of guest-physical to host-physical ranges, with
+//!   access permissions
+//! - Attaching devices, identified by their stream or requester ID, to
+//!   domains
+//! - Reporting of translation faults
+//!
+//! # Types
+//!
+//! - [`DomainId`] - The ID of a translation domain.
+//! - [`StreamId`] - The ID of a device in DMA requests.
+//! - [`DmaPerm`] - The access permissions of a mapping.
+//! - [`IommuError`] - The reason why an IOMMU operation failed.
+//! - [`IommuFault`] - A DMA request the IOMMU failed to translate.
+//!
+//! # Implementation
+//!
+//! To implement these APIs, use the [`api_impl`](crate::api_impl) attribute
+//! macro on an impl block:
+//!
+//! ```rust,ignore
+//! struct IommuIfImpl;
+//!
+//! #[axvisor_api::api_impl]
+//! impl axvisor_api::iommu::IommuIf for IommuIfImpl {
+//!     fn create_domain() -> Result<DomainId, IommuError> {
+//!         // Allocate a context of the IOMMU
+//!     }
+//!     // ... implement other functions
+//! }
+//! ```
+//!
+//! Every function has a default implementation reporting that the host has
+//! no IOMMU, so hosts without one only need an empty impl block:
+//!
+//! ```rust,ignore
+//! #[axvisor_api::api_impl]
+//! impl axvisor_api::iommu::IommuIf for IommuIfImpl {}
+//! ```
+//!
+//! For tests, the [`soft`] module provides a software model of an
+//! IOMMU to forward the API functions to.
+
+use core::fmt;
+
+use axaddrspace::GuestPhysAddr;
+use bitflags::bitflags;
+
+use crate::memory::PhysAddr;
+
+pub mod soft;
+
+/// Translation domain ID type.
+pub type DomainId = usize;
+
+/// Device stream ID type, e.g. an SMMU stream ID or a PCI requester ID.
+pub type StreamId = u32;
+
+bitflags! {
+    /// The access permissions of a DMA mapping, or the access of a DMA
+    /// request.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct DmaPerm: u8 {
+        /// Devices may read the memory.
+        const READ = 1 << 0;
+        /// Devices may write the memory.
+        const WRITE = 1 << 1;
+    }
+}
+
+/// The error returned when an IOMMU operation fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum IommuError {
+    /// The domain does not exist.
+    InvalidDomain,
+    /// The request is invalid, e.g. the range is empty or not page-aligned.
+    InvalidParam,
+    /// Part of the range is already mapped.
+    AlreadyMapped,
+    /// Part of the range is not mapped.
+    NotMapped,
+    /// The domain has devices attached, or the device is attached to another
+    /// domain.
+    Busy,
+    /// The device is not attached to the domain.
+    NotAttached,
+    /// There is not enough memory for the translation tables.
+    NoMemory,
+    /// The operation is not supported, e.g. the host has no IOMMU.
+    Unsupported,
+}
+
+impl fmt::Display for IommuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::InvalidDomain => "invalid domain",
+            Self::InvalidParam => "invalid parameter",
+            Self::AlreadyMapped => "range already mapped",
+            Self::NotMapped => "range not mapped",
+            Self::Busy => "domain or device busy",
+            Self::NotAttached => "device not attached",
+            Self::NoMemory => "out of memory",
+            Self::Unsupported => "operation not supported",
+        })
+    }
+}
+
+impl core::error::Error for IommuError {}
+
+/// The reason why a DMA request faulted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum IommuFaultKind {
+    /// The device is not attached to any domain.
+    NotAttached,
+    /// The address is not mapped in the domain of the device.
+    Translation,
+    /// The address is mapped without the permissions of the request.
+    Permission,
+}
+
+/// A DMA request the IOMMU failed to translate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IommuFault {
+    /// The device which issued the request.
+    pub stream_id: StreamId,
+    /// The domain of the device, if it is attached to one.
+    pub domain: Option<DomainId>,
+    /// The address of the request.
+    pub iova: GuestPhysAddr,
+    /// The access of the request.
+    pub access: DmaPerm,
+    /// The reason why the request faulted.
+    pub kind: IommuFaultKind,
+}
+
+/// The API trait for DMA mapping and IOMMU functionalities.
+///
+/// This trait defines the interface for programming the host IOMMU for
+/// passthrough devices. Implementations should be provided by the host
+/// system or HAL layer. The default implementations fail with
+/// [`IommuError::Unsupported`], as for hosts without an IOMMU.
+#[crate::api_def(mock, trace, stats, intercept)]
+pub trait IommuIf {
+    /// Create a translation domain, with no mappings and no devices.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(DomainId)` - The ID of the created domain.
+    /// - `Err(IommuError)` - The reason why the creation failed.
+    fn create_domain() -> Result<DomainId, IommuError> {
+        Err(IommuError::Unsupported)
+    }
+
+    /// Destroy a translation domain, removing all its mappings.
+    ///
+    /// # Arguments
+    ///
+    /// * `domain` - The domain to destroy.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IommuError::Busy`] if devices are still attached to the
+    /// domain.
+    fn destroy_domain(domain: DomainId) -> Result<(), IommuError> {
+        let _ = domain;
+        Err(IommuError::Unsupported)
+    }
+
+    /// Map a guest physical address range to a host physical address range
+    /// in a domain.
+    ///
+    /// # Arguments
+    ///
+    /// * `domain` - The domain to map the range in.
+    /// * `gpa` - The start of the guest physical address range, as seen by
+    ///   the devices.
+    /// * `hpa` - The start of the host physical address range.
+    /// * `size` - The size of the range in bytes.
+    /// * `perm` - The accesses allowed to the devices.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IommuError::InvalidParam`] if the range is empty, not
+    /// aligned to 4 KiB or overflows the address space, or if `perm` is
+    /// empty, or [`IommuError::AlreadyMapped`] if part of the range is
+    /// already mapped.
+    fn map(
+        domain: DomainId,
+        gpa: GuestPhysAddr,
+        hpa: PhysAddr,
+        size: usize,
+        perm: DmaPerm,
+    ) -> Result<(), IommuError> {
+        let _ = (domain, gpa, hpa, size, perm);
+        Err(IommuError::Unsupported)
+    }
+
+    /// Unmap a guest physical address range from a domain.
+    ///
+    /// The IOTLB entries of the range are invalidated before returning.
+    ///
+    /// # Arguments
+    ///
+    /// * `domain` - The domain to unmap the range from.
+    /// * `gpa` - The start of the guest physical address range.
+    /// * `size` - The size of the range in bytes.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IommuError::NotMapped`] if part of the range is not mapped,
+    /// in which case nothing is unmapped.
+    fn unmap(domain: DomainId, gpa: GuestPhysAddr, size: usize) -> Result<(), IommuError> {
+        let _ = (domain, gpa, size);
+        Err(IommuError::Unsupported)
+    }
+
+    /// Attach a device to a domain, so its DMA requests are translated by
+    /// the mappings of the domain.
+    ///
+    /// # Arguments
+    ///
+    /// * `domain` - The domain to attach the device to.
+    /// * `stream_id` - The stream or requester ID of the device.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IommuError::Busy`] if the device is attached to a domain.
+    fn attach_device(domain: DomainId, stream_id: StreamId) -> Result<(), IommuError> {
+        let _ = (domain, stream_id);
+        Err(IommuError::Unsupported)
+    }
+
+    /// Detach a device from a domain, blocking its DMA requests.
+    ///
+    /// # Arguments
+    ///
+    /// * `domain` - The domain the device is attached to.
+    /// * `stream_id` - The stream or requester ID of the device.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IommuError::NotAttached`] if the device is not attached to
+    /// the domain.
+    fn detach_device(domain: DomainId, stream_id: StreamId) -> Result<(), IommuError> {
+        let _ = (domain, stream_id);
+        Err(IommuError::Unsupported)
+    }
+
+    /// Fetch the oldest fault recorded by the IOMMU and not fetched yet.
+    ///
+    /// # Returns
+    ///
+    /// - `Some(IommuFault)` - The fault.
+    /// - `None` - If there is no pending fault, or the host has no IOMMU.
+    fn next_fault() -> Option<IommuFault> {
+        None
+    }
+}
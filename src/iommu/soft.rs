@@ -0,0 +1,258 @@
+// Copyright 2025 The Axvisor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A software model of an IOMMU, for tests.
+//!
+//! [`SoftIommu`] keeps the domains, mappings and attached devices in memory,
+//! and translates simulated DMA requests with [`SoftIommu::translate`],
+//! recording faults like a hardware IOMMU. Tests implement
+//! [`IommuIf`](super::IommuIf) by forwarding to a static instance:
+//!
+//! ```rust,ignore
+//! use axvisor_api::iommu::{soft::SoftIommu, *};
+//!
+//! static IOMMU: SoftIommu = SoftIommu::new();
+//!
+//! struct IommuIfImpl;
+//!
+//! #[axvisor_api::api_impl]
+//! impl IommuIf for IommuIfImpl {
+//!     fn create_domain() -> Result<DomainId, IommuError> {
+//!         IOMMU.create_domain()
+//!     }
+//!     // ... forward other functions
+//! }
+//! ```
+//!
+//! Mappings are kept per 4 KiB page, so the model is not suited to map large
+//! ranges.
+
+extern crate alloc;
+
+use alloc::collections::{BTreeMap, VecDeque};
+use core::ops::Range;
+
+use axaddrspace::GuestPhysAddr;
+use memory_addr::{MemoryAddr, PAGE_SIZE_4K, is_aligned_4k};
+
+use super::{DmaPerm, DomainId, IommuError, IommuFault, IommuFaultKind, StreamId};
+use crate::{memory::PhysAddr, sync::SpinLock};
+
+/// A translation domain of the model.
+#[derive(Default)]
+struct Domain {
+    /// The host physical page and the permissions of every mapped guest
+    /// physical page.
+    pages: BTreeMap<usize, (usize, DmaPerm)>,
+    /// The number of attached devices.
+    devices: usize,
+}
+
+/// The state of the model.
+struct State {
+    next_domain: DomainId,
+    domains: BTreeMap<DomainId, Domain>,
+    streams: BTreeMap<StreamId, DomainId>,
+    faults: VecDeque<IommuFault>,
+}
+
+/// A software model of an IOMMU.
+pub struct SoftIommu {
+    state: SpinLock<State>,
+}
+
+impl SoftIommu {
+    /// Create an IOMMU with no domains.
+    pub const fn new() -> Self {
+        Self {
+            state: SpinLock::new(State {
+                next_domain: 0,
+                domains: BTreeMap::new(),
+                streams: BTreeMap::new(),
+                faults: VecDeque::new(),
+            }),
+        }
+    }
+
+    /// Get the address range of a guest range, checking that it is
+    /// page-aligned, not empty and does not overflow.
+    fn range(gpa: GuestPhysAddr, size: usize) -> Result<Range<usize>, IommuError> {
+        let start = gpa.as_usize();
+        if size == 0 || !is_aligned_4k(start) || !is_aligned_4k(size) {
+            return Err(IommuError::InvalidParam);
+        }
+        let end = start.checked_add(size).ok_or(IommuError::InvalidParam)?;
+        Ok(start..end)
+    }
+
+    /// Create a translation domain, see
+    /// [`create_domain`](super::create_domain).
+    pub fn create_domain(&self) -> Result<DomainId, IommuError> {
+        let mut state = self.state.lock();
+        let domain = state.next_domain;
+        state.next_domain += 1;
+        state.domains.insert(domain, Domain::default());
+        Ok(domain)
+    }
+
+    /// Destroy a translation domain, see
+    /// [`destroy_domain`](super::destroy_domain).
+    pub fn destroy_domain(&self, domain: DomainId) -> Result<(), IommuError> {
+        let mut state = self.state.lock();
+        match state.domains.get(&domain) {
+            None => Err(IommuError::InvalidDomain),
+            Some(entry) if entry.devices > 0 => Err(IommuError::Busy),
+            Some(_) => {
+                state.domains.remove(&domain);
+                Ok(())
+            }
+        }
+    }
+
+    /// Map a guest physical address range, see [`map`](super::map).
+    pub fn map(
+        &self,
+        domain: DomainId,
+        gpa: GuestPhysAddr,
+        hpa: PhysAddr,
+        size: usize,
+        perm: DmaPerm,
+    ) -> Result<(), IommuError> {
+        let range = Self::range(gpa, size)?;
+        // The host range must not overflow either.
+        if perm.is_empty() || !hpa.is_aligned_4k() || hpa.as_usize().checked_add(size).is_none() {
+            return Err(IommuError::InvalidParam);
+        }
+        let mut state = self.state.lock();
+        let entry = state
+            .domains
+            .get_mut(&domain)
+            .ok_or(IommuError::InvalidDomain)?;
+        if entry.pages.range(range.clone()).next().is_some() {
+            return Err(IommuError::AlreadyMapped);
+        }
+        for (i, page) in range.step_by(PAGE_SIZE_4K).enumerate() {
+            entry
+                .pages
+                .insert(page, (hpa.as_usize() + i * PAGE_SIZE_4K, perm));
+        }
+        Ok(())
+    }
+
+    /// Unmap a guest physical address range, see [`unmap`](super::unmap).
+    pub fn unmap(
+        &self,
+        domain: DomainId,
+        gpa: GuestPhysAddr,
+        size: usize,
+    ) -> Result<(), IommuError> {
+        let range = Self::range(gpa, size)?;
+        let mut state = self.state.lock();
+        let entry = state
+            .domains
+            .get_mut(&domain)
+            .ok_or(IommuError::InvalidDomain)?;
+        if entry.pages.range(range.clone()).count() != size / PAGE_SIZE_4K {
+            return Err(IommuError::NotMapped);
+        }
+        for page in range.step_by(PAGE_SIZE_4K) {
+            entry.pages.remove(&page);
+        }
+        Ok(())
+    }
+
+    /// Attach a device to a domain, see
+    /// [`attach_device`](super::attach_device).
+    pub fn attach_device(&self, domain: DomainId, stream_id: StreamId) -> Result<(), IommuError> {
+        let mut state = self.state.lock();
+        if state.streams.contains_key(&stream_id) {
+            return Err(IommuError::Busy);
+        }
+        state
+            .domains
+            .get_mut(&domain)
+            .ok_or(IommuError::InvalidDomain)?
+            .devices += 1;
+        state.streams.insert(stream_id, domain);
+        Ok(())
+    }
+
+    /// Detach a device from a domain, see
+    /// [`detach_device`](super::detach_device).
+    pub fn detach_device(&self, domain: DomainId, stream_id: StreamId) -> Result<(), IommuError> {
+        let mut state = self.state.lock();
+        if state.streams.get(&stream_id) != Some(&domain) {
+            return Err(IommuError::NotAttached);
+        }
+        state.streams.remove(&stream_id);
+        if let Some(entry) = state.domains.get_mut(&domain) {
+            entry.devices -= 1;
+        }
+        Ok(())
+    }
+
+    /// Fetch the oldest fault not fetched yet, see
+    /// [`next_fault`](super::next_fault).
+    pub fn next_fault(&self) -> Option<IommuFault> {
+        self.state.lock().faults.pop_front()
+    }
+
+    /// Translate a DMA request of a device, as the hardware would.
+    ///
+    /// If the request faults, the fault is recorded, to be fetched with
+    /// [`next_fault`](Self::next_fault), and returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream_id` - The device issuing the request.
+    /// * `iova` - The address of the request.
+    /// * `access` - The access of the request.
+    pub fn translate(
+        &self,
+        stream_id: StreamId,
+        iova: GuestPhysAddr,
+        access: DmaPerm,
+    ) -> Result<PhysAddr, IommuFault> {
+        let mut state = self.state.lock();
+        let domain = state.streams.get(&stream_id).copied();
+        let result = match domain.and_then(|domain| state.domains.get(&domain)) {
+            None => Err(IommuFaultKind::NotAttached),
+            Some(entry) => {
+                let page = iova.align_down_4k().as_usize();
+                match entry.pages.get(&page) {
+                    None => Err(IommuFaultKind::Translation),
+                    Some((_, perm)) if !perm.contains(access) => Err(IommuFaultKind::Permission),
+                    Some((hpa, _)) => Ok(PhysAddr::from(hpa + iova.align_offset_4k())),
+                }
+            }
+        };
+        result.map_err(|kind| {
+            let fault = IommuFault {
+                stream_id,
+                domain,
+                iova,
+                access,
+                kind,
+            };
+            state.faults.push_back(fault);
+            fault
+        })
+    }
+}
+
+impl Default for SoftIommu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -98,6 +98,11 @@
 //! the trait, at the same level as the trait definition. The generated callers
 //! can be used to invoke the API functions, as demonstrated above.
 //!
+//! API functions may also have a default body in the trait. The default body
+//! is used as a fallback if the implementation does not provide the function,
+//! so new API functions can be added without breaking existing
+//! implementations.
+//!
 //! ## Implement APIs
 //!
 //! Defined APIs should be implemented somewhere, unless they are not used
@@ -145,15 +150,94 @@
 #![no_std]
 
 pub use axvisor_api_proc::{api_def, api_impl};
+pub use dynamic::{install, installed, uninstall};
 
 pub mod arch;
+pub mod dynamic;
 pub mod host;
+#[cfg(feature = "intercept")]
+pub mod intercept;
+pub mod iommu;
 pub mod memory;
+#[cfg(feature = "mock")]
+pub mod mock;
+#[cfg(feature = "stats")]
+pub mod stats;
+mod sync;
 pub mod time;
+#[cfg(feature = "trace")]
+pub mod trace;
 pub mod vmm;
 
+/// An API function, identified by the names of its trait and itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ApiFn {
+    /// The name of the API trait, like `MemoryIf`.
+    pub trait_name: &'static str,
+    /// The name of the API function, like `alloc_frame`.
+    pub fn_name: &'static str,
+}
+
+impl ApiFn {
+    /// Create an identifier of an API function.
+    pub const fn new(trait_name: &'static str, fn_name: &'static str) -> Self {
+        Self {
+            trait_name,
+            fn_name,
+        }
+    }
+}
+
+impl core::fmt::Display for ApiFn {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}::{}", self.trait_name, self.fn_name)
+    }
+}
+
+/// Check that all AxVisor APIs are implemented in the final binary.
+///
+/// Without this check, a missing [`api_impl`] block results in an undefined
+/// reference to every API function of the trait used anywhere, which is hard
+/// to read. This macro adds a reference to the registration marker of every
+/// API trait defined in this crate, so a missing implementation is reported
+/// once per trait, by a function whose symbol names the trait and all its
+/// functions:
+///
+/// ```text
+/// undefined symbol: __AxVisorApi_TimeIf__impl
+/// >>> referenced by ...:(AxVisorApi::TimeIf requires an #[api_impl] (current_ticks, ...))
+/// ```
+///
+/// Paths to additional API traits defined with [`api_def`] can be passed to
+/// check them as well. They must be written in full rather than through a
+/// `use` alias.
+///
+/// It's recommended to invoke this macro once in the entry point of the
+/// crate providing the implementations:
+///
+/// ```rust,ignore
+/// fn main() {
+///     axvisor_api::assert_all_apis_implemented!(my_component::MyComponentIf);
+///     // ...
+/// }
+/// ```
+#[macro_export]
+macro_rules! assert_all_apis_implemented {
+    ($($api:path),* $(,)?) => {{
+        $crate::__priv::assert_api_implemented!($crate::arch::ArchIf);
+        $crate::__priv::assert_api_implemented!($crate::host::HostIf);
+        $crate::__priv::assert_api_implemented!($crate::iommu::IommuIf);
+        $crate::__priv::assert_api_implemented!($crate::memory::MemoryIf);
+        $crate::__priv::assert_api_implemented!($crate::time::TimeIf);
+        $crate::__priv::assert_api_implemented!($crate::vmm::VmmIf);
+        $($crate::__priv::assert_api_implemented!($api);)*
+    }};
+}
+
 #[doc(hidden)]
 pub mod __priv {
+    pub use axvisor_api_proc::{assert_api_implemented, unsafe_api_fn};
+
     pub mod crate_interface {
         pub use crate_interface::{call_interface, def_interface, impl_interface};
     }
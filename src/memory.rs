@@ -23,6 +23,7 @@
 //! - Physical frame allocation for guest memory
 //! - Contiguous frame allocation for DMA and other hardware requirements
 //! - Address translation between physical and virtual addresses
+//! - Notifications of memory pressure, so components free reclaimable frames
 //!
 //! # Re-exports
 //!
@@ -33,6 +34,32 @@
 //!
 //! - [`PhysFrame`] - A physical frame that is automatically deallocated when
 //!   dropped.
+//! - [`PhysFrameRange`] - Contiguous physical frames that are automatically
+//!   deallocated when dropped.
+//! - [`SizedPhysFrame`] - A physical frame of a given [`PageSize`], e.g. a
+//!   2 MiB huge page, that is automatically deallocated when dropped.
+//! - [`SharedPhysFrame`] - A reference-counted physical frame, which can be
+//!   shared between address spaces and copied on write.
+//! - [`AllocError`] - The reason why an allocation failed.
+//! - [`PlacementHint`] - Where allocated frames should be placed.
+//! - [`HostMemoryRegion`] - A region of the host physical memory map, as
+//!   enumerated by [`host_memory_regions`].
+//! - [`MmioRegion`] - Device registers mapped by [`ioremap`], which are
+//!   unmapped when dropped.
+//! - [`PressureLevel`] - How low the host is on memory, as reported by
+//!   [`notify_memory_pressure`] to the handlers registered with
+//!   [`register_pressure_handler`].
+//!
+//! # Accounting
+//!
+//! The [`accounting`] module charges allocated frames to VMs, and enforces
+//! per-VM memory quotas. The RAII frame types but [`PhysFrame`] are charged to
+//! the VM which allocated them, i.e. the current VM unless an owner is given.
+//!
+//! # Shared Frames
+//!
+//! The [`frame_table`] module holds the reference counts of the frames owned
+//! by [`SharedPhysFrame`]s.
 //!
 //! # Implementation
 //!
@@ -51,14 +78,264 @@
 //! }
 //! ```
 
+extern crate alloc;
+
+use core::{fmt, iter::FusedIterator, slice};
+
+use bitflags::bitflags;
+
+use memory_addr::PAGE_SIZE_4K;
 pub use memory_addr::{PhysAddr, VirtAddr};
 
+use crate::{host::NumaNodeId, vmm::VMId};
+
+pub mod accounting;
+pub mod frame_table;
+mod pressure;
+
+pub use pressure::{
+    PressureHandlerToken, PressureLevel, notify_memory_pressure, register_pressure_handler,
+    unregister_pressure_handler,
+};
+
+/// The error returned when an allocation fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AllocError {
+    /// There is not enough free memory.
+    NoMemory,
+    /// The request is invalid, e.g. it asks for zero frames.
+    InvalidParam,
+    /// The requested address or size is not aligned as required.
+    Misaligned,
+    /// The requested alignment is not supported by the allocator.
+    UnsupportedAlignment,
+    /// The memory quota of the owner of the allocation is exhausted.
+    QuotaExceeded,
+}
+
+impl fmt::Display for AllocError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::NoMemory => "out of memory",
+            Self::InvalidParam => "invalid allocation request",
+            Self::Misaligned => "misaligned allocation request",
+            Self::UnsupportedAlignment => "unsupported alignment",
+            Self::QuotaExceeded => "memory quota exceeded",
+        })
+    }
+}
+
+impl core::error::Error for AllocError {}
+
+/// Where frames allocated with [`alloc_frame_with_hint`] and
+/// [`alloc_contiguous_frames_with_hint`] should be placed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum PlacementHint {
+    /// Anywhere.
+    #[default]
+    Any,
+    /// Preferably on the given NUMA node.
+    Node(NumaNodeId),
+    /// Preferably on the NUMA node of the given host CPU.
+    NearCpu(usize),
+    /// Below 4 GiB, for devices only capable of 32-bit DMA. Unlike the other
+    /// hints, this one is a requirement.
+    Dma32,
+}
+
+impl PlacementHint {
+    /// The end of the memory usable for 32-bit DMA.
+    const DMA32_LIMIT: u64 = 1 << 32;
+
+    /// Check whether frames at the given address and of the given size
+    /// satisfy the requirements of the hint.
+    pub fn is_satisfied_by(self, addr: PhysAddr, size: usize) -> bool {
+        match self {
+            Self::Dma32 => (addr.as_usize() as u64)
+                .checked_add(size as u64)
+                .is_some_and(|end| end <= Self::DMA32_LIMIT),
+            _ => true,
+        }
+    }
+}
+
+/// The type of a region of the host physical memory map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum HostMemoryKind {
+    /// RAM.
+    Ram,
+    /// RAM reserved by the host, e.g. for its image or its allocator, which
+    /// must not be handed to guests.
+    Reserved,
+    /// Memory-mapped I/O registers of devices.
+    Mmio,
+    /// Memory used by the firmware, e.g. ACPI tables or secure monitor.
+    Firmware,
+}
+
+bitflags! {
+    /// The flags of a region of the host physical memory map.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct HostMemoryFlags: u32 {
+        /// The region is mapped in the host virtual address space, so
+        /// [`phys_to_virt`] can be used.
+        const MAPPED = 1 << 0;
+        /// The frames of the region are managed by the frame allocator.
+        const ALLOCATABLE = 1 << 1;
+        /// The region is cacheable.
+        const CACHEABLE = 1 << 2;
+        /// The region may be passed through to guests, e.g. the registers of
+        /// a device not used by the host.
+        const PASSTHROUGH = 1 << 3;
+    }
+}
+
+/// A region of the host physical memory map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HostMemoryRegion {
+    /// The start of the region.
+    pub paddr: PhysAddr,
+    /// The size of the region in bytes.
+    pub size: usize,
+    /// The type of the region.
+    pub kind: HostMemoryKind,
+    /// The flags of the region.
+    pub flags: HostMemoryFlags,
+}
+
+impl HostMemoryRegion {
+    /// Get the end of the region (exclusive).
+    pub fn end(&self) -> PhysAddr {
+        self.paddr + self.size
+    }
+
+    /// Check whether the region contains an address.
+    pub fn contains(&self, addr: PhysAddr) -> bool {
+        self.paddr <= addr && addr < self.end()
+    }
+
+    /// Check whether the region contains the whole range `[addr, addr + size)`.
+    pub fn contains_range(&self, addr: PhysAddr, size: usize) -> bool {
+        self.paddr <= addr
+            && addr
+                .as_usize()
+                .checked_add(size)
+                .is_some_and(|end| end <= self.end().as_usize())
+    }
+}
+
+/// An iterator over the regions of the host physical memory map, returned
+/// by [`host_memory_regions`].
+#[derive(Debug, Clone)]
+pub struct HostMemoryRegions(slice::Iter<'static, HostMemoryRegion>);
+
+impl HostMemoryRegions {
+    /// Create an iterator over a memory map.
+    pub fn new(regions: &'static [HostMemoryRegion]) -> Self {
+        Self(regions.iter())
+    }
+
+    /// Create an iterator over an empty memory map.
+    pub fn empty() -> Self {
+        Self::new(&[])
+    }
+}
+
+impl Iterator for HostMemoryRegions {
+    type Item = HostMemoryRegion;
+
+    fn next(&mut self) -> Option<HostMemoryRegion> {
+        self.0.next().copied()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl DoubleEndedIterator for HostMemoryRegions {
+    fn next_back(&mut self) -> Option<HostMemoryRegion> {
+        self.0.next_back().copied()
+    }
+}
+
+impl ExactSizeIterator for HostMemoryRegions {}
+
+impl FusedIterator for HostMemoryRegions {}
+
+/// The memory attributes of a mapping of device memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MemAttr {
+    /// Device memory, allowing early write acknowledgement (e.g.
+    /// Device-nGnRE on AArch64). Suitable for most device registers.
+    Device,
+    /// Device memory, with strongly ordered accesses (e.g. Device-nGnRnE on
+    /// AArch64).
+    DeviceStrict,
+    /// Normal non-cacheable memory, e.g. for framebuffers.
+    NormalNonCacheable,
+    /// Normal cacheable memory.
+    Normal,
+}
+
+/// The sizes of the frames which can be allocated with
+/// [`alloc_frames_of_size`], matching the block sizes of stage-2 page tables.
+#[repr(usize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PageSize {
+    /// Size of 4 kilobytes (2<sup>12</sup> bytes).
+    Size4K = 0x1000,
+    /// Size of 2 megabytes (2<sup>21</sup> bytes).
+    Size2M = 0x20_0000,
+    /// Size of 1 gigabytes (2<sup>30</sup> bytes).
+    Size1G = 0x4000_0000,
+}
+
+impl PageSize {
+    /// Get the size in bytes.
+    pub const fn size(self) -> usize {
+        self as usize
+    }
+
+    /// Get the number of 4 KiB frames in a frame of this size.
+    pub const fn num_frames(self) -> usize {
+        self.size() / PAGE_SIZE_4K
+    }
+
+    /// Get the alignment of a frame of this size, as a power of 2 of the
+    /// 4 KiB frame size, as taken by [`alloc_contiguous_frames`].
+    pub const fn frame_align_pow2(self) -> usize {
+        self.num_frames().trailing_zeros() as usize
+    }
+
+    /// Whether this page size is larger than 4 KiB.
+    pub const fn is_huge(self) -> bool {
+        !matches!(self, Self::Size4K)
+    }
+
+    /// Check whether an address or a size is aligned to this page size.
+    pub const fn is_aligned(self, addr_or_size: usize) -> bool {
+        memory_addr::is_aligned(addr_or_size, self.size())
+    }
+}
+
+impl From<PageSize> for usize {
+    #[inline]
+    fn from(size: PageSize) -> usize {
+        size.size()
+    }
+}
+
 /// The API trait for memory allocation and address translation functionalities.
 ///
 /// This trait defines the core memory management interface required by the
 /// hypervisor. Implementations should be provided by the host system or HAL
 /// layer.
-#[crate::api_def]
+#[crate::api_def(mock, trace, stats, intercept)]
 pub trait MemoryIf {
     /// Allocate a single physical frame (4KB page).
     ///
@@ -74,7 +351,7 @@ pub trait MemoryIf {
     ///
     /// if let Some(frame) = alloc_frame() {
     ///     // Use the frame...
-    ///     dealloc_frame(frame);
+    ///     unsafe { dealloc_frame(frame) };
     /// }
     /// ```
     fn alloc_frame() -> Option<PhysAddr>;
@@ -97,6 +374,181 @@ pub trait MemoryIf {
     /// - `None` - If allocation fails.
     fn alloc_contiguous_frames(num_frames: usize, frame_align_pow2: usize) -> Option<PhysAddr>;
 
+    /// Allocate a physical frame, reporting why the allocation failed.
+    ///
+    /// The default implementation calls [`alloc_frame`] and reports any
+    /// failure as [`AllocError::NoMemory`]. Implementations able to tell the
+    /// causes apart should override it.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(PhysAddr)` - The physical address of the allocated frame.
+    /// - `Err(AllocError)` - The reason why the allocation failed.
+    fn try_alloc_frame() -> Result<PhysAddr, AllocError> {
+        alloc_frame().ok_or(AllocError::NoMemory)
+    }
+
+    /// Allocate a physical frame placed according to a hint.
+    ///
+    /// The default implementation ignores the preferred NUMA node, calls
+    /// [`try_alloc_frame`], and fails with [`AllocError::NoMemory`] if the
+    /// frame does not satisfy the requirements of the hint. Implementations
+    /// of NUMA-aware hosts should override it.
+    ///
+    /// # Arguments
+    ///
+    /// * `hint` - Where the frame should be placed.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(PhysAddr)` - The physical address of the allocated frame.
+    /// - `Err(AllocError)` - The reason why the allocation failed.
+    fn alloc_frame_with_hint(hint: PlacementHint) -> Result<PhysAddr, AllocError> {
+        let addr = try_alloc_frame()?;
+        if !hint.is_satisfied_by(addr, PAGE_SIZE_4K) {
+            // SAFETY: the frame was just allocated and is not used.
+            unsafe { dealloc_frame(addr) };
+            return Err(AllocError::NoMemory);
+        }
+        Ok(addr)
+    }
+
+    /// Allocate a physical frame filled with zeros.
+    ///
+    /// The default implementation calls [`alloc_frame`] and zeros the frame
+    /// through [`phys_to_virt`]. Implementations with pools of pre-zeroed
+    /// frames should override it to skip the extra zeroing.
+    ///
+    /// # Returns
+    ///
+    /// - `Some(PhysAddr)` - The physical address of the allocated frame.
+    /// - `None` - If allocation fails.
+    fn alloc_zeroed_frame() -> Option<PhysAddr> {
+        let addr = alloc_frame()?;
+        // SAFETY: the frame is newly allocated and mapped in the host virtual
+        // address space.
+        unsafe { core::ptr::write_bytes(phys_to_virt(addr).as_mut_ptr(), 0, PAGE_SIZE_4K) };
+        Some(addr)
+    }
+
+    /// Allocate a number of contiguous physical frames with a specified
+    /// alignment, reporting why the allocation failed.
+    ///
+    /// The default implementation rejects empty requests and alignments
+    /// which do not fit in an address, then calls [`alloc_contiguous_frames`]
+    /// and reports any failure as [`AllocError::NoMemory`].
+    ///
+    /// # Arguments
+    ///
+    /// * `num_frames` - The number of contiguous frames to allocate.
+    /// * `frame_align_pow2` - The alignment requirement as a power of 2
+    ///   (e.g., 0 for 4KB alignment, 1 for 8KB alignment).
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(PhysAddr)` - The physical address of the first allocated frame.
+    /// - `Err(AllocError)` - The reason why the allocation failed.
+    fn try_alloc_contiguous_frames(
+        num_frames: usize,
+        frame_align_pow2: usize,
+    ) -> Result<PhysAddr, AllocError> {
+        if num_frames == 0 {
+            return Err(AllocError::InvalidParam);
+        }
+        // The alignment in bytes, `PAGE_SIZE_4K << frame_align_pow2`, must be
+        // representable.
+        if frame_align_pow2 >= (usize::BITS - PAGE_SIZE_4K.trailing_zeros()) as usize {
+            return Err(AllocError::UnsupportedAlignment);
+        }
+        alloc_contiguous_frames(num_frames, frame_align_pow2).ok_or(AllocError::NoMemory)
+    }
+
+    /// Allocate a number of contiguous physical frames with a specified
+    /// alignment, placed according to a hint.
+    ///
+    /// The default implementation ignores the preferred NUMA node, calls
+    /// [`try_alloc_contiguous_frames`], and fails with
+    /// [`AllocError::NoMemory`] if the frames do not satisfy the requirements
+    /// of the hint. Implementations of NUMA-aware hosts should override it.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_frames` - The number of contiguous frames to allocate.
+    /// * `frame_align_pow2` - The alignment requirement as a power of 2
+    ///   (e.g., 0 for 4KB alignment, 1 for 8KB alignment).
+    /// * `hint` - Where the frames should be placed.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(PhysAddr)` - The physical address of the first allocated frame.
+    /// - `Err(AllocError)` - The reason why the allocation failed.
+    fn alloc_contiguous_frames_with_hint(
+        num_frames: usize,
+        frame_align_pow2: usize,
+        hint: PlacementHint,
+    ) -> Result<PhysAddr, AllocError> {
+        let addr = try_alloc_contiguous_frames(num_frames, frame_align_pow2)?;
+        if !hint.is_satisfied_by(addr, num_frames * PAGE_SIZE_4K) {
+            // SAFETY: the frames were just allocated and are not used.
+            unsafe { dealloc_contiguous_frames(addr, num_frames) };
+            return Err(AllocError::NoMemory);
+        }
+        Ok(addr)
+    }
+
+    /// Allocate a number of contiguous physical frames with a specified
+    /// alignment, filled with zeros.
+    ///
+    /// The default implementation calls [`alloc_contiguous_frames`] and zeros
+    /// the frames through [`phys_to_virt`]. Implementations with pools of
+    /// pre-zeroed frames should override it to skip the extra zeroing.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_frames` - The number of contiguous frames to allocate.
+    /// * `frame_align_pow2` - The alignment requirement as a power of 2
+    ///   (e.g., 0 for 4KB alignment, 1 for 8KB alignment).
+    ///
+    /// # Returns
+    ///
+    /// - `Some(PhysAddr)` - The physical address of the first allocated frame.
+    /// - `None` - If allocation fails.
+    fn alloc_zeroed_contiguous_frames(
+        num_frames: usize,
+        frame_align_pow2: usize,
+    ) -> Option<PhysAddr> {
+        let addr = alloc_contiguous_frames(num_frames, frame_align_pow2)?;
+        // SAFETY: the frames are newly allocated and mapped contiguously in
+        // the host virtual address space.
+        unsafe {
+            core::ptr::write_bytes(
+                phys_to_virt(addr).as_mut_ptr(),
+                0,
+                num_frames * PAGE_SIZE_4K,
+            )
+        };
+        Some(addr)
+    }
+
+    /// Allocate a physical frame of the given size, aligned to its size, so
+    /// it can be mapped with a single block mapping.
+    ///
+    /// The default implementation allocates the 4 KiB frames making up the
+    /// frame with [`try_alloc_contiguous_frames`]. Implementations with
+    /// dedicated huge page pools should override it.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - The size of the frame to allocate.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(PhysAddr)` - The physical address of the allocated frame.
+    /// - `Err(AllocError)` - The reason why the allocation failed.
+    fn alloc_frames_of_size(size: PageSize) -> Result<PhysAddr, AllocError> {
+        try_alloc_contiguous_frames(size.num_frames(), size.frame_align_pow2())
+    }
+
     /// Deallocate a frame previously allocated by [`alloc_frame`].
     ///
     /// # Arguments
@@ -109,7 +561,7 @@ pub trait MemoryIf {
     /// - The address was previously returned by [`alloc_frame`].
     /// - The frame has not been deallocated yet.
     /// - No references to the frame's memory exist after deallocation.
-    fn dealloc_frame(addr: PhysAddr);
+    unsafe fn dealloc_frame(addr: PhysAddr);
 
     /// Deallocate contiguous frames previously allocated by
     /// [`alloc_contiguous_frames`].
@@ -126,7 +578,29 @@ pub trait MemoryIf {
     ///   call.
     /// - The frames have not been deallocated yet.
     /// - No references to the frames' memory exist after deallocation.
-    fn dealloc_contiguous_frames(first_addr: PhysAddr, num_frames: usize);
+    unsafe fn dealloc_contiguous_frames(first_addr: PhysAddr, num_frames: usize);
+
+    /// Deallocate a frame previously allocated by [`alloc_frames_of_size`].
+    ///
+    /// The default implementation deallocates the 4 KiB frames making up the
+    /// frame with [`dealloc_contiguous_frames`], and must be overridden
+    /// together with [`alloc_frames_of_size`].
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - The physical address of the frame to deallocate.
+    /// * `size` - The size of the frame.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that:
+    /// - The address and size match a previous [`alloc_frames_of_size`]
+    ///   call.
+    /// - The frame has not been deallocated yet.
+    /// - No references to the frame's memory exist after deallocation.
+    unsafe fn dealloc_frames_of_size(addr: PhysAddr, size: PageSize) {
+        unsafe { dealloc_contiguous_frames(addr, size.num_frames()) }
+    }
 
     /// Convert a physical address to a virtual address.
     ///
@@ -163,22 +637,193 @@ pub trait MemoryIf {
     ///
     /// May panic if the virtual address is not mapped.
     fn virt_to_phys(addr: VirtAddr) -> PhysAddr;
+
+    /// Convert a physical address to a virtual address, or `None` if it's not
+    /// mapped.
+    ///
+    /// Use it instead of [`phys_to_virt`] to translate untrusted addresses,
+    /// e.g. supplied by guests. The default implementation assumes every
+    /// address is mapped and calls [`phys_to_virt`]. Implementations able to
+    /// tell unmapped addresses should override it.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - The physical address to convert.
+    ///
+    /// # Returns
+    ///
+    /// - `Some(VirtAddr)` - The corresponding virtual address.
+    /// - `None` - If the physical address is not mapped.
+    fn try_phys_to_virt(addr: PhysAddr) -> Option<VirtAddr> {
+        Some(phys_to_virt(addr))
+    }
+
+    /// Convert a virtual address to a physical address, or `None` if it's not
+    /// mapped.
+    ///
+    /// Use it instead of [`virt_to_phys`] to translate untrusted addresses.
+    /// The default implementation assumes every address is mapped and calls
+    /// [`virt_to_phys`]. Implementations able to tell unmapped addresses
+    /// should override it.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - The virtual address to convert.
+    ///
+    /// # Returns
+    ///
+    /// - `Some(PhysAddr)` - The corresponding physical address.
+    /// - `None` - If the virtual address is not mapped.
+    fn try_virt_to_phys(addr: VirtAddr) -> Option<PhysAddr> {
+        Some(virt_to_phys(addr))
+    }
+
+    /// Convert a physical address range to a virtual address, checking that
+    /// the whole range is mapped contiguously.
+    ///
+    /// The default implementation checks that the first and the last bytes
+    /// of the range are mapped at the same offset, which is sufficient for a
+    /// linear mapping without holes. Implementations whose mapping has holes
+    /// should override it.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - The start of the physical address range.
+    /// * `len` - The length of the range in bytes.
+    ///
+    /// # Returns
+    ///
+    /// - `Some(VirtAddr)` - The virtual address of the start of the range.
+    /// - `None` - If some part of the range is not mapped contiguously.
+    fn try_phys_to_virt_range(addr: PhysAddr, len: usize) -> Option<VirtAddr> {
+        let start = try_phys_to_virt(addr)?;
+        if let Some(last_offset) = len.checked_sub(1) {
+            let last = try_phys_to_virt(addr.as_usize().checked_add(last_offset)?.into())?;
+            if last.as_usize().checked_sub(start.as_usize()) != Some(last_offset) {
+                return None;
+            }
+        }
+        Some(start)
+    }
+
+    /// Convert a virtual address range to a physical address, checking that
+    /// the whole range is mapped contiguously.
+    ///
+    /// The default implementation checks that the first and the last bytes
+    /// of the range are mapped at the same offset, which is sufficient for a
+    /// linear mapping without holes. Implementations whose mapping has holes
+    /// should override it.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - The start of the virtual address range.
+    /// * `len` - The length of the range in bytes.
+    ///
+    /// # Returns
+    ///
+    /// - `Some(PhysAddr)` - The physical address of the start of the range.
+    /// - `None` - If some part of the range is not mapped contiguously.
+    fn try_virt_to_phys_range(addr: VirtAddr, len: usize) -> Option<PhysAddr> {
+        let start = try_virt_to_phys(addr)?;
+        if let Some(last_offset) = len.checked_sub(1) {
+            let last = try_virt_to_phys(addr.as_usize().checked_add(last_offset)?.into())?;
+            if last.as_usize().checked_sub(start.as_usize()) != Some(last_offset) {
+                return None;
+            }
+        }
+        Some(start)
+    }
+
+    /// Enumerate the regions of the host physical memory map.
+    ///
+    /// Components use it to validate the ranges passed through to guests,
+    /// and avoid handing memory reserved by the host to guests. The regions
+    /// are sorted by address and do not overlap.
+    ///
+    /// The default implementation reports an empty memory map.
+    ///
+    /// # Returns
+    ///
+    /// An iterator over the regions of the host physical memory map.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// use axvisor_api::memory::{HostMemoryFlags, host_memory_regions};
+    ///
+    /// let can_pass_through = host_memory_regions().any(|region| {
+    ///     region.flags.contains(HostMemoryFlags::PASSTHROUGH)
+    ///         && region.contains_range(mmio_base, mmio_size)
+    /// });
+    /// ```
+    fn host_memory_regions() -> HostMemoryRegions {
+        HostMemoryRegions::empty()
+    }
+
+    /// Map a range of device memory into the host virtual address space.
+    ///
+    /// Use [`ioremap`] instead, which unmaps the range when it is no longer
+    /// used.
+    ///
+    /// The default implementation maps nothing and returns `None`, as it
+    /// cannot apply the requested attributes. Hosts supporting device memory
+    /// mappings must override it together with [`unmap_mmio`].
+    ///
+    /// # Arguments
+    ///
+    /// * `paddr` - The start of the physical address range.
+    /// * `size` - The size of the range in bytes.
+    /// * `attr` - The memory attributes of the mapping.
+    ///
+    /// # Returns
+    ///
+    /// - `Some(VirtAddr)` - The virtual address of the start of the range.
+    /// - `None` - If the range cannot be mapped.
+    fn map_mmio(paddr: PhysAddr, size: usize, attr: MemAttr) -> Option<VirtAddr> {
+        let _ = (paddr, size, attr);
+        None
+    }
+
+    /// Unmap a range of device memory mapped by [`map_mmio`].
+    ///
+    /// The default implementation does nothing, as the default
+    /// implementation of [`map_mmio`] does not map anything.
+    ///
+    /// # Arguments
+    ///
+    /// * `vaddr` - The virtual address returned by [`map_mmio`].
+    /// * `size` - The size of the range in bytes.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that:
+    /// - The address and size match a previous [`map_mmio`] call.
+    /// - The range has not been unmapped yet.
+    /// - The mapping is not accessed after it is unmapped.
+    unsafe fn unmap_mmio(vaddr: VirtAddr, size: usize) {
+        let _ = (vaddr, size);
+    }
 }
 
 /// [`AxMmHal`](axaddrspace::AxMmHal) implementation by axvisor_api.
 ///
 /// This struct provides an implementation of the `AxMmHal` trait from the
 /// `axaddrspace` crate, delegating to the axvisor_api memory functions.
+///
+/// The frames are not charged to any VM by [`accounting`], as the VM a page
+/// table frame belongs to is not known here.
 #[doc(hidden)]
 pub struct AxMmHalApiImpl;
 
 impl axaddrspace::AxMmHal for AxMmHalApiImpl {
     fn alloc_frame() -> Option<PhysAddr> {
-        alloc_frame()
+        try_alloc_frame().ok()
     }
 
     fn dealloc_frame(addr: PhysAddr) {
-        dealloc_frame(addr)
+        // SAFETY: `axaddrspace` only deallocates the frames it allocated with
+        // `alloc_frame`, once.
+        unsafe { dealloc_frame(addr) }
     }
 
     fn phys_to_virt(addr: PhysAddr) -> VirtAddr {
@@ -196,6 +841,9 @@ impl axaddrspace::AxMmHal for AxMmHalApiImpl {
 /// allocation. When a `PhysFrame` is dropped, it automatically deallocates
 /// the underlying physical memory.
 ///
+/// Unlike the other RAII frame types, the frame is not charged to any VM by
+/// [`accounting`].
+///
 /// # Example
 ///
 /// ```rust,ignore
@@ -208,3 +856,593 @@ impl axaddrspace::AxMmHal for AxMmHalApiImpl {
 /// // The frame will be automatically deallocated when it goes out of scope
 /// ```
 pub type PhysFrame = axaddrspace::PhysFrame<AxMmHalApiImpl>;
+
+/// Contiguous physical frames which will be automatically deallocated when
+/// dropped.
+///
+/// A `PhysFrameRange` owns the address, the number of frames and the
+/// alignment of an allocation made with [`try_alloc_contiguous_frames`], so
+/// the frames are always deallocated with the count they were allocated with.
+///
+/// The frames are charged to their owner VM by [`accounting`], and uncharged
+/// when dropped. The contents of the frames are unspecified after allocation.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use axvisor_api::memory::PhysFrameRange;
+///
+/// let mut frames = PhysFrameRange::alloc(4, 0)?;
+/// frames.fill(0);
+/// let dma_addr = frames.start_paddr();
+/// // The frames are deallocated when `frames` goes out of scope
+/// ```
+#[derive(Debug)]
+pub struct PhysFrameRange {
+    owner: VMId,
+    start_paddr: PhysAddr,
+    num_frames: usize,
+    frame_align_pow2: usize,
+}
+
+impl PhysFrameRange {
+    /// Allocate a number of contiguous physical frames with a specified
+    /// alignment, charged to the current VM.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_frames` - The number of contiguous frames to allocate.
+    /// * `frame_align_pow2` - The alignment requirement as a power of 2
+    ///   (e.g., 0 for 4KB alignment, 1 for 8KB alignment).
+    pub fn alloc(num_frames: usize, frame_align_pow2: usize) -> Result<Self, AllocError> {
+        Self::alloc_for(crate::vmm::current_vm_id(), num_frames, frame_align_pow2)
+    }
+
+    /// Allocate a number of contiguous physical frames with a specified
+    /// alignment, charged to a VM.
+    ///
+    /// See [`accounting::alloc_contiguous_frames_for`].
+    pub fn alloc_for(
+        owner: VMId,
+        num_frames: usize,
+        frame_align_pow2: usize,
+    ) -> Result<Self, AllocError> {
+        let start_paddr =
+            accounting::alloc_contiguous_frames_for(owner, num_frames, frame_align_pow2)?;
+        Ok(Self {
+            owner,
+            start_paddr,
+            num_frames,
+            frame_align_pow2,
+        })
+    }
+
+    /// Take the ownership of contiguous frames allocated with
+    /// [`accounting::alloc_contiguous_frames_for`].
+    ///
+    /// # Safety
+    ///
+    /// The frames must have been allocated by
+    /// [`accounting::alloc_contiguous_frames_for`] with the given arguments,
+    /// and must not be deallocated elsewhere.
+    pub unsafe fn from_raw_parts(
+        owner: VMId,
+        start_paddr: PhysAddr,
+        num_frames: usize,
+        frame_align_pow2: usize,
+    ) -> Self {
+        Self {
+            owner,
+            start_paddr,
+            num_frames,
+            frame_align_pow2,
+        }
+    }
+
+    /// Give up the ownership of the frames without deallocating nor
+    /// uncharging them, returning the owner, the address, the number of
+    /// frames and the alignment.
+    pub fn into_raw_parts(self) -> (VMId, PhysAddr, usize, usize) {
+        let parts = (
+            self.owner,
+            self.start_paddr,
+            self.num_frames,
+            self.frame_align_pow2,
+        );
+        core::mem::forget(self);
+        parts
+    }
+
+    /// Get the VM the frames are charged to.
+    pub const fn owner(&self) -> VMId {
+        self.owner
+    }
+
+    /// Get the physical address of the first frame.
+    pub const fn start_paddr(&self) -> PhysAddr {
+        self.start_paddr
+    }
+
+    /// Get the physical address right after the last frame.
+    pub fn end_paddr(&self) -> PhysAddr {
+        self.start_paddr + self.size()
+    }
+
+    /// Get the number of frames.
+    pub const fn num_frames(&self) -> usize {
+        self.num_frames
+    }
+
+    /// Get the alignment of the frames, as a power of 2 of the frame size.
+    pub const fn frame_align_pow2(&self) -> usize {
+        self.frame_align_pow2
+    }
+
+    /// Get the size of the frames in bytes.
+    pub const fn size(&self) -> usize {
+        self.num_frames * PAGE_SIZE_4K
+    }
+
+    /// Get a pointer to the frames in the host virtual address space.
+    pub fn as_ptr(&self) -> *const u8 {
+        phys_to_virt(self.start_paddr).as_ptr()
+    }
+
+    /// Get a mutable pointer to the frames in the host virtual address space.
+    pub fn as_mut_ptr(&mut self) -> *mut u8 {
+        phys_to_virt(self.start_paddr).as_mut_ptr()
+    }
+
+    /// Get the contents of the frames.
+    pub fn as_slice(&self) -> &[u8] {
+        // SAFETY: the frames are owned and mapped contiguously in the host
+        // virtual address space.
+        unsafe { slice::from_raw_parts(self.as_ptr(), self.size()) }
+    }
+
+    /// Get the mutable contents of the frames.
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        // SAFETY: the frames are owned and mapped contiguously in the host
+        // virtual address space.
+        unsafe { slice::from_raw_parts_mut(self.as_mut_ptr(), self.size()) }
+    }
+
+    /// Fill the frames with a byte.
+    pub fn fill(&mut self, byte: u8) {
+        self.as_mut_slice().fill(byte);
+    }
+}
+
+impl Drop for PhysFrameRange {
+    fn drop(&mut self) {
+        // SAFETY: the frames are owned, and were allocated with this count
+        // for the owner.
+        unsafe {
+            accounting::dealloc_contiguous_frames_for(self.owner, self.start_paddr, self.num_frames)
+        }
+    }
+}
+
+/// A physical frame of a given [`PageSize`] which will be automatically
+/// deallocated when dropped.
+///
+/// Unlike [`PhysFrame`], the frame may be a huge page, allocated with
+/// [`alloc_frames_of_size`], so guest memory backed by such frames can be
+/// mapped with block mappings in stage-2 page tables.
+///
+/// The frame is charged to its owner VM by [`accounting`], and uncharged
+/// when dropped. The contents of the frame are unspecified after allocation.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use axvisor_api::memory::{PageSize, SizedPhysFrame};
+///
+/// let frame = SizedPhysFrame::alloc(PageSize::Size2M)?;
+/// assert!(PageSize::Size2M.is_aligned(frame.start_paddr().as_usize()));
+/// // The frame is deallocated when `frame` goes out of scope
+/// ```
+#[derive(Debug)]
+pub struct SizedPhysFrame {
+    owner: VMId,
+    start_paddr: PhysAddr,
+    page_size: PageSize,
+}
+
+impl SizedPhysFrame {
+    /// Allocate a physical frame of the given size, charged to the current
+    /// VM.
+    pub fn alloc(page_size: PageSize) -> Result<Self, AllocError> {
+        Self::alloc_for(crate::vmm::current_vm_id(), page_size)
+    }
+
+    /// Allocate a physical frame of the given size, charged to a VM.
+    ///
+    /// See [`accounting::alloc_frames_of_size_for`].
+    pub fn alloc_for(owner: VMId, page_size: PageSize) -> Result<Self, AllocError> {
+        let start_paddr = accounting::alloc_frames_of_size_for(owner, page_size)?;
+        debug_assert!(page_size.is_aligned(start_paddr.as_usize()));
+        Ok(Self {
+            owner,
+            start_paddr,
+            page_size,
+        })
+    }
+
+    /// Take the ownership of a frame allocated with
+    /// [`accounting::alloc_frames_of_size_for`].
+    ///
+    /// # Safety
+    ///
+    /// The frame must have been allocated by
+    /// [`accounting::alloc_frames_of_size_for`] with the given arguments, and
+    /// must not be deallocated elsewhere.
+    pub unsafe fn from_raw_parts(owner: VMId, start_paddr: PhysAddr, page_size: PageSize) -> Self {
+        Self {
+            owner,
+            start_paddr,
+            page_size,
+        }
+    }
+
+    /// Give up the ownership of the frame without deallocating nor
+    /// uncharging it, returning the owner, the address and the size.
+    pub fn into_raw_parts(self) -> (VMId, PhysAddr, PageSize) {
+        let parts = (self.owner, self.start_paddr, self.page_size);
+        core::mem::forget(self);
+        parts
+    }
+
+    /// Get the VM the frame is charged to.
+    pub const fn owner(&self) -> VMId {
+        self.owner
+    }
+
+    /// Get the physical address of the frame.
+    pub const fn start_paddr(&self) -> PhysAddr {
+        self.start_paddr
+    }
+
+    /// Get the size of the frame.
+    pub const fn page_size(&self) -> PageSize {
+        self.page_size
+    }
+
+    /// Get the size of the frame in bytes.
+    pub const fn size(&self) -> usize {
+        self.page_size.size()
+    }
+
+    /// Get a pointer to the frame in the host virtual address space.
+    pub fn as_ptr(&self) -> *const u8 {
+        phys_to_virt(self.start_paddr).as_ptr()
+    }
+
+    /// Get a mutable pointer to the frame in the host virtual address space.
+    pub fn as_mut_ptr(&mut self) -> *mut u8 {
+        phys_to_virt(self.start_paddr).as_mut_ptr()
+    }
+
+    /// Get the contents of the frame.
+    pub fn as_slice(&self) -> &[u8] {
+        // SAFETY: the frame is owned and mapped contiguously in the host
+        // virtual address space.
+        unsafe { slice::from_raw_parts(self.as_ptr(), self.size()) }
+    }
+
+    /// Get the mutable contents of the frame.
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        // SAFETY: the frame is owned and mapped contiguously in the host
+        // virtual address space.
+        unsafe { slice::from_raw_parts_mut(self.as_mut_ptr(), self.size()) }
+    }
+
+    /// Fill the frame with a byte.
+    pub fn fill(&mut self, byte: u8) {
+        self.as_mut_slice().fill(byte);
+    }
+}
+
+impl Drop for SizedPhysFrame {
+    fn drop(&mut self) {
+        // SAFETY: the frame is owned, and was allocated with this size for
+        // the owner.
+        unsafe {
+            accounting::dealloc_frames_of_size_for(self.owner, self.start_paddr, self.page_size)
+        }
+    }
+}
+
+/// A reference-counted physical frame which will be automatically
+/// deallocated when the last handle is dropped.
+///
+/// Cloning a `SharedPhysFrame` creates another handle to the same frame, so
+/// it can be mapped into the address spaces of several VMs, e.g. for shared
+/// memory or a copy-on-write clone of a VM. The reference counts are kept in
+/// the [`frame_table`].
+///
+/// The contents of a shared frame can only be modified through a unique
+/// handle, see [`get_mut`](Self::get_mut) and
+/// [`make_unique`](Self::make_unique).
+///
+/// The frame is charged to the VM which allocated it by [`accounting`], and
+/// uncharged when the last handle is dropped.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use axvisor_api::memory::SharedPhysFrame;
+///
+/// let mut frame = SharedPhysFrame::alloc_zeroed()?;
+/// let mut child = frame.clone();
+/// // On a write fault in the child VM, copy the frame
+/// child.make_unique()?[0] = 1;
+/// assert_ne!(child.start_paddr(), frame.start_paddr());
+/// ```
+#[derive(Debug)]
+pub struct SharedPhysFrame {
+    owner: VMId,
+    start_paddr: PhysAddr,
+}
+
+impl SharedPhysFrame {
+    /// Allocate a physical frame charged to the current VM, with a single
+    /// handle.
+    ///
+    /// The contents of the frame are unspecified after allocation.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the allocator returns a frame which is already shared.
+    pub fn alloc() -> Result<Self, AllocError> {
+        let owner = crate::vmm::current_vm_id();
+        Ok(Self::new(owner, accounting::alloc_frame_for(owner)?))
+    }
+
+    /// Allocate a physical frame filled with zeros charged to the current
+    /// VM, with a single handle.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the allocator returns a frame which is already shared.
+    pub fn alloc_zeroed() -> Result<Self, AllocError> {
+        let owner = crate::vmm::current_vm_id();
+        Ok(Self::new(owner, accounting::alloc_zeroed_frame_for(owner)?))
+    }
+
+    /// Add a newly allocated frame to the frame table.
+    fn new(owner: VMId, start_paddr: PhysAddr) -> Self {
+        frame_table::insert(start_paddr);
+        Self { owner, start_paddr }
+    }
+
+    /// Get the VM the frame is charged to.
+    pub const fn owner(&self) -> VMId {
+        self.owner
+    }
+
+    /// Get the physical address of the frame.
+    pub const fn start_paddr(&self) -> PhysAddr {
+        self.start_paddr
+    }
+
+    /// Get the number of handles to the frame.
+    pub fn ref_count(&self) -> usize {
+        frame_table::ref_count(self.start_paddr)
+    }
+
+    /// Whether this is the only handle to the frame.
+    pub fn is_unique(&self) -> bool {
+        self.ref_count() == 1
+    }
+
+    /// Get a pointer to the frame in the host virtual address space.
+    pub fn as_ptr(&self) -> *const u8 {
+        phys_to_virt(self.start_paddr).as_ptr()
+    }
+
+    /// Get the contents of the frame.
+    pub fn as_slice(&self) -> &[u8] {
+        // SAFETY: the frame is mapped in the host virtual address space, and
+        // only modified through unique handles.
+        unsafe { slice::from_raw_parts(self.as_ptr(), PAGE_SIZE_4K) }
+    }
+
+    /// Get the mutable contents of the frame, or `None` if the frame is
+    /// shared.
+    pub fn get_mut(&mut self) -> Option<&mut [u8]> {
+        if !self.is_unique() {
+            return None;
+        }
+        // SAFETY: the frame is mapped in the host virtual address space, and
+        // this is the only handle to it.
+        Some(unsafe {
+            slice::from_raw_parts_mut(phys_to_virt(self.start_paddr).as_mut_ptr(), PAGE_SIZE_4K)
+        })
+    }
+
+    /// Get the mutable contents of the frame, first copying it to a newly
+    /// allocated frame if it is shared.
+    ///
+    /// After a copy, this handle owns the new frame, charged to the current
+    /// VM, and the other handles keep the original one.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of [`accounting::alloc_frame_for`] if the copy
+    /// cannot be allocated, in which case the handle is unchanged.
+    pub fn make_unique(&mut self) -> Result<&mut [u8], AllocError> {
+        if !self.is_unique() {
+            let mut copy = Self::alloc()?;
+            // The frame is not modified while shared.
+            copy.get_mut()
+                .expect("newly allocated frame is shared")
+                .copy_from_slice(self.as_slice());
+            *self = copy;
+        }
+        Ok(self.get_mut().expect("unique frame is shared"))
+    }
+}
+
+impl Clone for SharedPhysFrame {
+    fn clone(&self) -> Self {
+        frame_table::acquire(self.start_paddr);
+        Self {
+            owner: self.owner,
+            start_paddr: self.start_paddr,
+        }
+    }
+}
+
+impl Drop for SharedPhysFrame {
+    fn drop(&mut self) {
+        if frame_table::release(self.start_paddr) {
+            // SAFETY: this was the last handle to the frame, which was
+            // allocated with `alloc_frame` for the owner.
+            unsafe { accounting::dealloc_frame_for(self.owner, self.start_paddr) }
+        }
+    }
+}
+
+mod private {
+    pub trait Sealed {}
+}
+
+/// A value which can be read from or written to an [`MmioRegion`].
+///
+/// This trait is sealed, and implemented for `u8`, `u16`, `u32` and `u64`.
+pub trait MmioValue: Copy + private::Sealed {}
+
+macro_rules! impl_mmio_value {
+    ($($ty:ty),*) => {
+        $(
+            impl private::Sealed for $ty {}
+            impl MmioValue for $ty {}
+        )*
+    };
+}
+
+impl_mmio_value!(u8, u16, u32, u64);
+
+/// A range of device memory mapped into the host virtual address space,
+/// which will be automatically unmapped when dropped.
+///
+/// The registers are accessed with volatile reads and writes at offsets
+/// from the start of the range.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use axvisor_api::memory::{MemAttr, ioremap};
+///
+/// let gicd = ioremap(get_host_gicd_base(), 0x1_0000, MemAttr::Device).unwrap();
+/// let typer: u32 = gicd.read(0x4);
+/// gicd.write(0x0, 1u32);
+/// // The registers are unmapped when `gicd` goes out of scope
+/// ```
+#[derive(Debug)]
+pub struct MmioRegion {
+    paddr: PhysAddr,
+    vaddr: VirtAddr,
+    size: usize,
+    attr: MemAttr,
+}
+
+impl MmioRegion {
+    /// Get the start of the physical address range.
+    pub const fn paddr(&self) -> PhysAddr {
+        self.paddr
+    }
+
+    /// Get the start of the mapping in the host virtual address space.
+    pub const fn vaddr(&self) -> VirtAddr {
+        self.vaddr
+    }
+
+    /// Get the size of the range in bytes.
+    pub const fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Get the memory attributes of the mapping.
+    pub const fn attr(&self) -> MemAttr {
+        self.attr
+    }
+
+    /// Get a pointer to a register, checking its bounds and alignment.
+    #[track_caller]
+    fn register<T: MmioValue>(&self, offset: usize) -> *mut T {
+        let size = size_of::<T>();
+        assert!(
+            offset.checked_add(size).is_some_and(|end| end <= self.size),
+            "MMIO access at offset {offset:#x} of size {size} out of range {:#x}",
+            self.size
+        );
+        assert!(
+            offset % align_of::<T>() == 0,
+            "misaligned MMIO access at offset {offset:#x} of size {size}"
+        );
+        (self.vaddr + offset).as_mut_ptr_of()
+    }
+
+    /// Read a register with a volatile read.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the register is out of the range, or is not aligned to its
+    /// size.
+    #[track_caller]
+    pub fn read<T: MmioValue>(&self, offset: usize) -> T {
+        // SAFETY: the register is mapped, in bounds and aligned.
+        unsafe { self.register::<T>(offset).read_volatile() }
+    }
+
+    /// Write a register with a volatile write.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the register is out of the range, or is not aligned to its
+    /// size.
+    #[track_caller]
+    pub fn write<T: MmioValue>(&self, offset: usize, value: T) {
+        // SAFETY: the register is mapped, in bounds and aligned.
+        unsafe { self.register::<T>(offset).write_volatile(value) }
+    }
+}
+
+impl Drop for MmioRegion {
+    fn drop(&mut self) {
+        // SAFETY: the mapping is owned, and is no longer accessed.
+        unsafe { unmap_mmio(self.vaddr, self.size) }
+    }
+}
+
+/// Map a range of device memory into the host virtual address space.
+///
+/// # Arguments
+///
+/// * `paddr` - The start of the physical address range.
+/// * `size` - The size of the range in bytes.
+/// * `attr` - The memory attributes of the mapping, usually
+///   [`MemAttr::Device`].
+///
+/// # Returns
+///
+/// - `Some(MmioRegion)` - The mapping, which is unmapped when dropped.
+/// - `None` - If the range cannot be mapped.
+pub fn ioremap(paddr: PhysAddr, size: usize, attr: MemAttr) -> Option<MmioRegion> {
+    let vaddr = map_mmio(paddr, size, attr)?;
+    Some(MmioRegion {
+        paddr,
+        vaddr,
+        size,
+        attr,
+    })
+}
+
+/// Unmap a range of device memory mapped by [`ioremap`].
+///
+/// This is the same as dropping the region.
+pub fn iounmap(region: MmioRegion) {
+    drop(region);
+}
@@ -0,0 +1,321 @@
+// Copyright 2025 The Axvisor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Per-VM memory accounting and quotas.
+//!
+//! The functions of this module wrap the frame allocation APIs of
+//! [`memory`](super), charging the allocated frames to a VM, either the
+//! current one, as reported by
+//! [`vmm::current_vm_id`](crate::vmm::current_vm_id), or an explicit owner.
+//! The number of frames charged to every VM is tracked, and allocations
+//! exceeding the limit set for the VM fail with
+//! [`AllocError::QuotaExceeded`]:
+//!
+//! ```rust,ignore
+//! use axvisor_api::memory::accounting;
+//!
+//! accounting::set_limit(vm_id, Some(0x4_0000)); // 1 GiB
+//! let frame = accounting::alloc_frame_for(vm_id)?;
+//! assert_eq!(accounting::usage(vm_id).frames, 1);
+//! unsafe { accounting::dealloc_frame_for(vm_id, frame) };
+//! ```
+//!
+//! Frames must be deallocated with the functions of this module and the same
+//! owner, so they are uncharged. Frames obtained otherwise, e.g. from a
+//! device pool, can be accounted with [`charge`] and [`uncharge`].
+//!
+//! The RAII frame types of [`memory`](super), e.g.
+//! [`PhysFrameRange`](super::PhysFrameRange), are allocated with this module
+//! too, charged to the current VM, and uncharged from the same VM when
+//! dropped. So are the frames of the
+//! [memory balloons](crate::vmm::balloon), which are uncharged when released
+//! by the guest and charged again when reclaimed.
+//!
+//! Use [`usage`] or [`snapshot`] to read the usage, and [`remove`] to forget
+//! a destroyed VM.
+
+extern crate alloc;
+
+use alloc::{collections::BTreeMap, vec::Vec};
+
+use super::{AllocError, PageSize, PhysAddr};
+use crate::{sync::SpinLock, vmm::VMId};
+
+/// The memory usage of a VM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VmMemoryUsage {
+    /// The VM.
+    pub vm_id: VMId,
+    /// The number of frames charged to the VM.
+    pub frames: usize,
+    /// The maximum number of frames charged to the VM at a time.
+    pub peak_frames: usize,
+    /// The maximum number of frames which can be charged to the VM, if any.
+    pub limit: Option<usize>,
+}
+
+impl VmMemoryUsage {
+    const fn new(vm_id: VMId) -> Self {
+        Self {
+            vm_id,
+            frames: 0,
+            peak_frames: 0,
+            limit: None,
+        }
+    }
+}
+
+/// The usage of every VM with frames charged or a limit set.
+static ACCOUNTS: SpinLock<BTreeMap<VMId, VmMemoryUsage>> = SpinLock::new(BTreeMap::new());
+
+/// Set the maximum number of frames which can be charged to a VM, or remove
+/// the limit with `None`.
+///
+/// Frames already charged are kept even if they exceed the new limit, only
+/// the following charges fail.
+pub fn set_limit(vm_id: VMId, limit: Option<usize>) {
+    ACCOUNTS
+        .lock()
+        .entry(vm_id)
+        .or_insert(VmMemoryUsage::new(vm_id))
+        .limit = limit;
+}
+
+/// Get the memory usage of a VM.
+pub fn usage(vm_id: VMId) -> VmMemoryUsage {
+    ACCOUNTS
+        .lock()
+        .get(&vm_id)
+        .copied()
+        .unwrap_or(VmMemoryUsage::new(vm_id))
+}
+
+/// Get the memory usage of all VMs with frames charged or a limit set,
+/// ordered by VM ID.
+pub fn snapshot() -> Vec<VmMemoryUsage> {
+    ACCOUNTS.lock().values().copied().collect()
+}
+
+/// Forget a VM, returning its last memory usage.
+///
+/// This should be called when the VM is destroyed, after all its frames are
+/// deallocated.
+pub fn remove(vm_id: VMId) -> Option<VmMemoryUsage> {
+    ACCOUNTS.lock().remove(&vm_id)
+}
+
+/// Charge a number of frames to a VM.
+///
+/// # Errors
+///
+/// Returns [`AllocError::QuotaExceeded`] if the frames would exceed the limit
+/// of the VM, in which case nothing is charged.
+pub fn charge(vm_id: VMId, num_frames: usize) -> Result<(), AllocError> {
+    reserve(vm_id, num_frames)?;
+    record_peak(vm_id);
+    Ok(())
+}
+
+/// Charge a number of frames to a VM, without updating its peak usage, as
+/// the allocation may still fail.
+fn reserve(vm_id: VMId, num_frames: usize) -> Result<(), AllocError> {
+    let mut accounts = ACCOUNTS.lock();
+    let usage = accounts.entry(vm_id).or_insert(VmMemoryUsage::new(vm_id));
+    let frames = usage
+        .frames
+        .checked_add(num_frames)
+        .filter(|&frames| usage.limit.is_none_or(|limit| frames <= limit))
+        .ok_or(AllocError::QuotaExceeded)?;
+    usage.frames = frames;
+    Ok(())
+}
+
+/// Update the peak usage of a VM after a successful allocation.
+fn record_peak(vm_id: VMId) {
+    if let Some(usage) = ACCOUNTS.lock().get_mut(&vm_id) {
+        usage.peak_frames = usage.peak_frames.max(usage.frames);
+    }
+}
+
+/// Uncharge a number of frames previously charged to a VM.
+pub fn uncharge(vm_id: VMId, num_frames: usize) {
+    if let Some(usage) = ACCOUNTS.lock().get_mut(&vm_id) {
+        debug_assert!(usage.frames >= num_frames, "uncharging uncharged frames");
+        usage.frames = usage.frames.saturating_sub(num_frames);
+    }
+}
+
+/// Allocate a physical frame charged to the current VM.
+///
+/// See [`alloc_frame_for`].
+pub fn alloc_frame() -> Result<PhysAddr, AllocError> {
+    alloc_frame_for(crate::vmm::current_vm_id())
+}
+
+/// Allocate a physical frame charged to a VM.
+///
+/// # Errors
+///
+/// Returns [`AllocError::QuotaExceeded`] if the limit of the VM is reached,
+/// or the error of [`try_alloc_frame`](super::try_alloc_frame).
+pub fn alloc_frame_for(vm_id: VMId) -> Result<PhysAddr, AllocError> {
+    reserve(vm_id, 1)?;
+    let addr = super::try_alloc_frame().inspect_err(|_| uncharge(vm_id, 1))?;
+    record_peak(vm_id);
+    Ok(addr)
+}
+
+/// Allocate a physical frame filled with zeros charged to the current VM.
+///
+/// See [`alloc_zeroed_frame_for`].
+pub fn alloc_zeroed_frame() -> Result<PhysAddr, AllocError> {
+    alloc_zeroed_frame_for(crate::vmm::current_vm_id())
+}
+
+/// Allocate a physical frame filled with zeros charged to a VM.
+///
+/// # Errors
+///
+/// Returns [`AllocError::QuotaExceeded`] if the limit of the VM is reached,
+/// or [`AllocError::NoMemory`] if
+/// [`alloc_zeroed_frame`](super::alloc_zeroed_frame) fails.
+pub fn alloc_zeroed_frame_for(vm_id: VMId) -> Result<PhysAddr, AllocError> {
+    reserve(vm_id, 1)?;
+    let addr = super::alloc_zeroed_frame()
+        .ok_or(AllocError::NoMemory)
+        .inspect_err(|_| uncharge(vm_id, 1))?;
+    record_peak(vm_id);
+    Ok(addr)
+}
+
+/// Allocate a number of contiguous physical frames charged to the current
+/// VM.
+///
+/// See [`alloc_contiguous_frames_for`].
+pub fn alloc_contiguous_frames(
+    num_frames: usize,
+    frame_align_pow2: usize,
+) -> Result<PhysAddr, AllocError> {
+    alloc_contiguous_frames_for(crate::vmm::current_vm_id(), num_frames, frame_align_pow2)
+}
+
+/// Allocate a number of contiguous physical frames charged to a VM.
+///
+/// # Errors
+///
+/// Returns [`AllocError::QuotaExceeded`] if the frames would exceed the limit
+/// of the VM, or the error of
+/// [`try_alloc_contiguous_frames`](super::try_alloc_contiguous_frames).
+pub fn alloc_contiguous_frames_for(
+    vm_id: VMId,
+    num_frames: usize,
+    frame_align_pow2: usize,
+) -> Result<PhysAddr, AllocError> {
+    reserve(vm_id, num_frames)?;
+    let addr = super::try_alloc_contiguous_frames(num_frames, frame_align_pow2)
+        .inspect_err(|_| uncharge(vm_id, num_frames))?;
+    record_peak(vm_id);
+    Ok(addr)
+}
+
+/// Allocate a physical frame of the given size charged to the current VM.
+///
+/// See [`alloc_frames_of_size_for`].
+pub fn alloc_frames_of_size(size: PageSize) -> Result<PhysAddr, AllocError> {
+    alloc_frames_of_size_for(crate::vmm::current_vm_id(), size)
+}
+
+/// Allocate a physical frame of the given size charged to a VM.
+///
+/// The frame is charged as the number of 4 KiB frames it spans.
+///
+/// # Errors
+///
+/// Returns [`AllocError::QuotaExceeded`] if the frame would exceed the limit
+/// of the VM, or the error of
+/// [`alloc_frames_of_size`](super::alloc_frames_of_size).
+pub fn alloc_frames_of_size_for(vm_id: VMId, size: PageSize) -> Result<PhysAddr, AllocError> {
+    reserve(vm_id, size.num_frames())?;
+    let addr =
+        super::alloc_frames_of_size(size).inspect_err(|_| uncharge(vm_id, size.num_frames()))?;
+    record_peak(vm_id);
+    Ok(addr)
+}
+
+/// Deallocate a frame charged to the current VM.
+///
+/// # Safety
+///
+/// See [`dealloc_frame_for`].
+pub unsafe fn dealloc_frame(addr: PhysAddr) {
+    unsafe { dealloc_frame_for(crate::vmm::current_vm_id(), addr) }
+}
+
+/// Deallocate a frame charged to a VM.
+///
+/// # Safety
+///
+/// The frame must have been allocated by [`alloc_frame_for`] or
+/// [`alloc_zeroed_frame_for`] for the same VM, or by [`alloc_frame`] or
+/// [`alloc_zeroed_frame`] in the context of the VM, and the requirements of
+/// [`memory::dealloc_frame`](super::dealloc_frame) apply.
+pub unsafe fn dealloc_frame_for(vm_id: VMId, addr: PhysAddr) {
+    unsafe { super::dealloc_frame(addr) };
+    uncharge(vm_id, 1);
+}
+
+/// Deallocate contiguous frames charged to the current VM.
+///
+/// # Safety
+///
+/// See [`dealloc_contiguous_frames_for`].
+pub unsafe fn dealloc_contiguous_frames(first_addr: PhysAddr, num_frames: usize) {
+    unsafe { dealloc_contiguous_frames_for(crate::vmm::current_vm_id(), first_addr, num_frames) }
+}
+
+/// Deallocate contiguous frames charged to a VM.
+///
+/// # Safety
+///
+/// The frames must have been allocated by [`alloc_contiguous_frames_for`]
+/// for the same VM, or by [`alloc_contiguous_frames`] in the context of the
+/// VM, and the requirements of
+/// [`memory::dealloc_contiguous_frames`](super::dealloc_contiguous_frames)
+/// apply.
+pub unsafe fn dealloc_contiguous_frames_for(vm_id: VMId, first_addr: PhysAddr, num_frames: usize) {
+    unsafe { super::dealloc_contiguous_frames(first_addr, num_frames) };
+    uncharge(vm_id, num_frames);
+}
+
+/// Deallocate a frame of the given size charged to the current VM.
+///
+/// # Safety
+///
+/// See [`dealloc_frames_of_size_for`].
+pub unsafe fn dealloc_frames_of_size(addr: PhysAddr, size: PageSize) {
+    unsafe { dealloc_frames_of_size_for(crate::vmm::current_vm_id(), addr, size) }
+}
+
+/// Deallocate a frame of the given size charged to a VM.
+///
+/// # Safety
+///
+/// The frame must have been allocated by [`alloc_frames_of_size_for`] for
+/// the same VM and size, or by [`alloc_frames_of_size`] in the context of the
+/// VM, and the requirements of
+/// [`memory::dealloc_frames_of_size`](super::dealloc_frames_of_size) apply.
+pub unsafe fn dealloc_frames_of_size_for(vm_id: VMId, addr: PhysAddr, size: PageSize) {
+    unsafe { super::dealloc_frames_of_size(addr, size) };
+    uncharge(vm_id, size.num_frames());
+}
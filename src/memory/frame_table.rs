@@ -0,0 +1,107 @@
+// Copyright 2025 The Axvisor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The metadata table of shared physical frames.
+//!
+//! Every frame owned by [`SharedPhysFrame`](super::SharedPhysFrame)s has an
+//! entry in the table, holding the number of handles to the frame. The entry
+//! is created when the frame is allocated, and removed when the last handle
+//! is dropped and the frame is deallocated:
+//!
+//! ```rust,ignore
+//! use axvisor_api::memory::{SharedPhysFrame, frame_table};
+//!
+//! let frame = SharedPhysFrame::alloc()?;
+//! let shared = frame.clone();
+//! assert_eq!(frame_table::ref_count(frame.start_paddr()), 2);
+//! ```
+//!
+//! Frames owned otherwise, e.g. by a [`PhysFrame`](super::PhysFrame), are not
+//! in the table.
+
+extern crate alloc;
+
+use alloc::{
+    collections::{BTreeMap, btree_map::Entry},
+    vec::Vec,
+};
+
+use super::PhysAddr;
+use crate::sync::SpinLock;
+
+/// The metadata of a shared physical frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct FrameMeta {
+    /// The number of handles to the frame.
+    pub ref_count: usize,
+}
+
+/// The metadata of every shared frame, by physical address.
+static FRAME_TABLE: SpinLock<BTreeMap<PhysAddr, FrameMeta>> = SpinLock::new(BTreeMap::new());
+
+/// Get the metadata of a frame, or `None` if it is not shared.
+pub fn frame_meta(paddr: PhysAddr) -> Option<FrameMeta> {
+    FRAME_TABLE.lock().get(&paddr).copied()
+}
+
+/// Get the number of handles to a frame, or 0 if it is not shared.
+pub fn ref_count(paddr: PhysAddr) -> usize {
+    frame_meta(paddr).map_or(0, |meta| meta.ref_count)
+}
+
+/// Get the metadata of all shared frames, ordered by physical address.
+pub fn snapshot() -> Vec<(PhysAddr, FrameMeta)> {
+    FRAME_TABLE
+        .lock()
+        .iter()
+        .map(|(&paddr, &meta)| (paddr, meta))
+        .collect()
+}
+
+/// Add a newly allocated frame to the table, with a single handle.
+///
+/// # Panics
+///
+/// Panics if the frame is already in the table, i.e. the allocator returned
+/// a frame which is still in use, leaving the existing entry unchanged.
+pub(super) fn insert(paddr: PhysAddr) {
+    match FRAME_TABLE.lock().entry(paddr) {
+        Entry::Vacant(entry) => {
+            entry.insert(FrameMeta { ref_count: 1 });
+        }
+        Entry::Occupied(_) => panic!("frame {paddr:?} allocated while already shared"),
+    }
+}
+
+/// Add a handle to a frame in the table.
+pub(super) fn acquire(paddr: PhysAddr) {
+    let mut table = FRAME_TABLE.lock();
+    let meta = table.get_mut(&paddr).expect("frame not shared");
+    meta.ref_count = meta.ref_count.checked_add(1).expect("too many handles");
+}
+
+/// Remove a handle to a frame in the table, returning whether it was the
+/// last one, in which case the frame is removed from the table.
+pub(super) fn release(paddr: PhysAddr) -> bool {
+    let mut table = FRAME_TABLE.lock();
+    let meta = table.get_mut(&paddr).expect("frame not shared");
+    meta.ref_count -= 1;
+    if meta.ref_count == 0 {
+        table.remove(&paddr);
+        true
+    } else {
+        false
+    }
+}
@@ -0,0 +1,184 @@
+// Copyright 2025 The Axvisor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Memory pressure notifications.
+
+extern crate alloc;
+
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::sync::SpinLock;
+
+/// How low the host is on memory, from the least to the most severe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PressureLevel {
+    /// Free memory is getting low. Components should drop caches which are
+    /// cheap to rebuild.
+    Low,
+    /// Free memory is low. Components should free all reclaimable frames.
+    Medium,
+    /// Allocations are about to fail. Components should free memory by any
+    /// means, e.g. by inflating the balloons of guests.
+    Critical,
+}
+
+impl PressureLevel {
+    /// All levels, from the least to the most severe.
+    const ALL: [Self; 3] = [Self::Low, Self::Medium, Self::Critical];
+}
+
+/// Handler token type for pressure handler unregistration.
+///
+/// This token is returned when registering a pressure handler and can be
+/// used to unregister it.
+pub type PressureHandlerToken = usize;
+
+/// A memory pressure handler, returning the number of frames it freed.
+type PressureHandler = Arc<dyn Fn(PressureLevel) -> usize + Send + Sync>;
+
+/// A registered handler.
+struct Registered {
+    token: PressureHandlerToken,
+    level: PressureLevel,
+    handler: PressureHandler,
+}
+
+/// The registered handlers, sorted by token.
+///
+/// Nothing is allocated nor freed while the lock is held, so that
+/// [`notify_memory_pressure`] can be called from within the allocator of the
+/// host while a handler is being registered or unregistered on the same CPU.
+static HANDLERS: SpinLock<Vec<Registered>> = SpinLock::new(Vec::new());
+
+/// The token of the next registered handler, only updated with
+/// [`HANDLERS`] locked so that tokens are registered in order.
+static NEXT_TOKEN: AtomicUsize = AtomicUsize::new(0);
+
+/// Register a handler called when the host reports memory pressure of the
+/// given level or more severe.
+///
+/// # Arguments
+///
+/// * `level` - The least severe level the handler is called for.
+/// * `callback` - The function to call on memory pressure. It receives the
+///   reported level, and returns the number of frames it freed. It must not
+///   allocate memory, see [`notify_memory_pressure`].
+///
+/// # Returns
+///
+/// A [`PressureHandlerToken`] that can be used to unregister the handler with
+/// [`unregister_pressure_handler`].
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use axvisor_api::memory::{PressureLevel, register_pressure_handler};
+///
+/// let token = register_pressure_handler(PressureLevel::Low, Box::new(|_level| {
+///     page_cache.shrink()
+/// }));
+/// ```
+pub fn register_pressure_handler(
+    level: PressureLevel,
+    callback: Box<dyn Fn(PressureLevel) -> usize + Send + Sync + 'static>,
+) -> PressureHandlerToken {
+    let handler: PressureHandler = callback.into();
+    // A larger vector, allocated without the lock held if the registered
+    // handlers do not fit in theirs.
+    let mut spare = Vec::new();
+    loop {
+        let mut handlers = HANDLERS.lock();
+        if handlers.len() == handlers.capacity() && spare.capacity() > handlers.len() {
+            spare.append(&mut handlers);
+            core::mem::swap(&mut *handlers, &mut spare);
+        }
+        if handlers.len() < handlers.capacity() {
+            let token = NEXT_TOKEN.fetch_add(1, Ordering::Relaxed);
+            handlers.push(Registered {
+                token,
+                level,
+                handler,
+            });
+            drop(handlers);
+            drop(spare);
+            return token;
+        }
+        let capacity = (handlers.capacity() * 2).max(4);
+        drop(handlers);
+        spare = Vec::with_capacity(capacity);
+    }
+}
+
+/// Unregister a pressure handler, returning whether it was registered.
+///
+/// The handler may still be running if pressure is being reported
+/// concurrently.
+pub fn unregister_pressure_handler(token: PressureHandlerToken) -> bool {
+    let mut handlers = HANDLERS.lock();
+    let removed = handlers
+        .binary_search_by_key(&token, |registered| registered.token)
+        .ok()
+        .map(|index| handlers.remove(index));
+    // Free the handler without the lock held.
+    drop(handlers);
+    removed.is_some()
+}
+
+/// Report memory pressure to the hypervisor components.
+///
+/// This should be called by the host when its frame allocator runs low, so
+/// reclaimable frames are freed before [`alloc_frame`](super::alloc_frame)
+/// starts failing.
+///
+/// The handlers registered for `level` or a less severe level are called,
+/// those registered for the least severe levels first, so cheap reclaim is
+/// tried before more disruptive one. Handlers are called without any lock
+/// held, so they may deallocate frames and unregister handlers.
+///
+/// Handlers must NOT allocate memory, neither frames nor from the heap (which
+/// includes registering handlers), as they run while the host is out of
+/// memory and may be called from within its allocator. This function does not
+/// allocate either.
+///
+/// # Returns
+///
+/// The total number of frames freed by the handlers.
+pub fn notify_memory_pressure(level: PressureLevel) -> usize {
+    let mut freed = 0usize;
+    for handler_level in PressureLevel::ALL.into_iter().filter(|l| *l <= level) {
+        // Look the handlers up one at a time by token, so no snapshot of them
+        // has to be allocated and none is called with the lock held.
+        let mut next_token = 0;
+        while let Some((token, handler)) = next_handler(handler_level, next_token) {
+            freed = freed.saturating_add(handler(level));
+            next_token = token + 1;
+        }
+    }
+    freed
+}
+
+/// Find the first handler registered for exactly `level` whose token is at
+/// least `from`.
+fn next_handler(
+    level: PressureLevel,
+    from: PressureHandlerToken,
+) -> Option<(PressureHandlerToken, PressureHandler)> {
+    let handlers = HANDLERS.lock();
+    let start = handlers.partition_point(|registered| registered.token < from);
+    handlers[start..]
+        .iter()
+        .find(|registered| registered.level == level)
+        .map(|registered| (registered.token, registered.handler.clone()))
+}
@@ -0,0 +1,432 @@
+// Copyright 2025 The Axvisor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Mock implementations of the AxVisor APIs for unit tests.
+//!
+//! This module is only available with the `mock` feature. It's intended to be
+//! enabled in the `dev-dependencies` of component crates, together with the
+//! `axvisor_api_mock` crate, which links the mocks into their tests:
+//!
+//! ```toml
+//! [dev-dependencies]
+//! axvisor_api = { version = "0.2", features = ["mock"] }
+//! axvisor_api_mock = "0.2"
+//! ```
+//!
+//! # Overview
+//!
+//! For every API trait defined with `#[api_def(mock)]`, a `mock` module is
+//! generated next to the trait, which contains:
+//! - A [`MockFn`] static for every API function, with the same name as the
+//!   function.
+//! - An implementation of the trait dispatching every API function to its
+//!   [`MockFn`], see [Implementation](#implementation).
+//! - `reset` and `verify` functions, which reset or verify all [`MockFn`]s of
+//!   the trait.
+//!
+//! All API traits of this crate have mocks, so a test of a component can
+//! control and check the APIs it calls without implementing them:
+//!
+//! ```rust,ignore
+//! use axvisor_api::vmm;
+//!
+//! #[test]
+//! fn test_timer_injects_interrupt() {
+//!     let _guard = axvisor_api::mock::lock();
+//!     vmm::mock::reset();
+//!
+//!     vmm::mock::current_vm_id.set(|()| 1);
+//!     vmm::mock::inject_interrupt
+//!         .expect(|&(vm_id, vcpu_id, vector)| vm_id == 1 && vcpu_id == 0 && vector == 0x20)
+//!         .times(1);
+//!
+//!     // ... code under test calling `vmm::inject_interrupt`
+//!
+//!     vmm::mock::verify();
+//! }
+//! ```
+//!
+//! # Behavior of Mocked Functions
+//!
+//! When a mocked function is called, its arguments are packed into a tuple
+//! and passed to the closure set by [`MockFn::set`]. Without such a closure,
+//! the default body of the function is used if the trait provides one,
+//! otherwise `Default::default()` is returned if the return type implements
+//! [`Default`], otherwise the call panics.
+//!
+//! Mocks are global, so tests using them should be serialized with
+//! [`lock`] and start with resetting the mocks they use, or simply hold a
+//! [`fixture`], which also resets the mocks of all API traits of this crate
+//! when the test ends, even by panicking.
+//!
+//! # Implementation
+//!
+//! The mock implementations of the API traits are never exported at link
+//! time, so enabling the `mock` feature, e.g. through feature unification,
+//! does not clash with the implementations of a binary, nor with the test
+//! implementations of this crate. The mocks are linked by
+//! `axvisor_api_mock`, which implements every API trait of this crate by
+//! calling its mock, and must be referenced from the crate under test:
+//!
+//! ```rust,ignore
+//! #[cfg(test)]
+//! extern crate axvisor_api_mock;
+//! ```
+
+extern crate alloc;
+
+use alloc::{boxed::Box, format, string::String, vec::Vec};
+use core::marker::PhantomData;
+
+use crate::sync::{SpinLock, SpinLockGuard};
+
+/// A guard serializing tests using mocks, see [`lock`].
+pub struct MockGuard(#[allow(dead_code)] SpinLockGuard<'static, ()>);
+
+/// Acquire the global lock serializing tests using mocks.
+///
+/// Mocks are global state, so tests using them must not run concurrently.
+/// Acquire the lock at the beginning of such tests and hold the returned
+/// guard until the end.
+pub fn lock() -> MockGuard {
+    static LOCK: SpinLock<()> = SpinLock::new(());
+    MockGuard(LOCK.lock())
+}
+
+/// A guard serializing a test using mocks, which resets the mocks of all API
+/// traits of this crate when dropped, see [`fixture`].
+pub struct MockFixture(#[allow(dead_code)] MockGuard);
+
+/// Acquire the global lock serializing tests using mocks, like [`lock`], and
+/// reset the mocks of all API traits of this crate.
+///
+/// The mocks are reset again when the returned fixture is dropped, so a
+/// failing test does not leave its mocks behind for the next one:
+///
+/// ```rust,ignore
+/// #[test]
+/// fn test_allocation_failure() {
+///     let _fixture = axvisor_api::mock::fixture();
+///     axvisor_api::memory::mock::alloc_frame.set(|()| None);
+///
+///     // ... code under test
+/// }
+/// ```
+pub fn fixture() -> MockFixture {
+    let guard = lock();
+    reset_all();
+    MockFixture(guard)
+}
+
+impl Drop for MockFixture {
+    fn drop(&mut self) {
+        // The lock is released after this, when the guard is dropped.
+        reset_all();
+    }
+}
+
+/// Reset the mocks of all API traits of this crate.
+fn reset_all() {
+    crate::arch::mock::reset();
+    crate::host::mock::reset();
+    crate::iommu::mock::reset();
+    crate::memory::mock::reset();
+    crate::time::mock::reset();
+    crate::vmm::mock::reset();
+}
+
+/// How many times an expectation should be matched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Times {
+    AtLeastOnce,
+    Exactly(usize),
+}
+
+/// An expectation on the calls to a mocked function.
+struct Expectation<A> {
+    matcher: Box<dyn Fn(&A) -> bool + Send>,
+    times: Times,
+    matched: usize,
+}
+
+impl<A> Expectation<A> {
+    fn saturated(&self) -> bool {
+        matches!(self.times, Times::Exactly(n) if self.matched >= n)
+    }
+
+    fn satisfied(&self) -> bool {
+        match self.times {
+            Times::AtLeastOnce => self.matched > 0,
+            Times::Exactly(n) => self.matched == n,
+        }
+    }
+}
+
+/// The state of a mocked function.
+struct MockState<A, R> {
+    behavior: Option<Box<dyn FnMut(A) -> R + Send>>,
+    calls: Vec<Option<A>>,
+    expectations: Vec<Expectation<A>>,
+}
+
+/// A mocked API function.
+///
+/// `A` is the tuple of the argument types of the function, and `R` is its
+/// return type. See the [module-level documentation](self) for details.
+pub struct MockFn<A, R> {
+    name: &'static str,
+    state: SpinLock<MockState<A, R>>,
+}
+
+impl<A, R> MockFn<A, R> {
+    /// Create a mocked function with the given name, used in panic messages.
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            state: SpinLock::new(MockState {
+                behavior: None,
+                calls: Vec::new(),
+                expectations: Vec::new(),
+            }),
+        }
+    }
+
+    /// Get the name of the mocked function.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn state(&self) -> SpinLockGuard<'_, MockState<A, R>> {
+        self.state.lock()
+    }
+
+    /// Set the closure called when the mocked function is called.
+    ///
+    /// The closure receives the arguments of the call as a tuple.
+    pub fn set(&self, behavior: impl FnMut(A) -> R + Send + 'static) {
+        self.state().behavior = Some(Box::new(behavior));
+    }
+
+    /// Reset the mocked function, clearing its closure, recorded calls and
+    /// expectations.
+    pub fn reset(&self) {
+        let mut state = self.state();
+        state.behavior = None;
+        state.calls.clear();
+        state.expectations.clear();
+    }
+
+    /// Get the number of calls to the mocked function.
+    pub fn call_count(&self) -> usize {
+        self.state().calls.len()
+    }
+
+    /// Get the arguments of the recorded calls to the mocked function.
+    ///
+    /// Arguments are only recorded if the argument tuple implements
+    /// [`Clone`], otherwise this function always returns an empty vector,
+    /// while [`call_count`](Self::call_count) still counts the calls.
+    pub fn calls(&self) -> Vec<A>
+    where
+        A: Clone,
+    {
+        self.state().calls.iter().flatten().cloned().collect()
+    }
+
+    /// Expect calls to the mocked function whose arguments match the given
+    /// predicate.
+    ///
+    /// By default, the expectation is satisfied if it's matched at least
+    /// once, use [`ExpectationBuilder::times`] to expect an exact number of
+    /// calls. Once any expectation is set, calls matching no expectation, or
+    /// only expectations already matched the expected number of times, panic.
+    /// Use [`verify`](Self::verify) to check that all expectations are
+    /// satisfied.
+    pub fn expect(
+        &self,
+        matcher: impl Fn(&A) -> bool + Send + 'static,
+    ) -> ExpectationBuilder<'_, A, R> {
+        let mut state = self.state();
+        state.expectations.push(Expectation {
+            matcher: Box::new(matcher),
+            times: Times::AtLeastOnce,
+            matched: 0,
+        });
+        ExpectationBuilder {
+            mock: self,
+            index: state.expectations.len() - 1,
+        }
+    }
+
+    /// Verify that all expectations on the mocked function are satisfied.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any expectation is not satisfied.
+    pub fn verify(&self) {
+        let unsatisfied = self
+            .state()
+            .expectations
+            .iter()
+            .enumerate()
+            .filter(|(_, expectation)| !expectation.satisfied())
+            .map(|(index, expectation)| {
+                format!(
+                    "\n  expectation #{index}: expected {:?} calls, got {}",
+                    expectation.times, expectation.matched
+                )
+            })
+            .collect::<String>();
+
+        assert!(
+            unsatisfied.is_empty(),
+            "unsatisfied expectations of `{}`:{unsatisfied}",
+            self.name
+        );
+    }
+
+    /// Call the mocked function.
+    ///
+    /// `recorded` is the copy of the arguments to record, if any, and
+    /// `fallback` is called if no closure is set.
+    #[doc(hidden)]
+    pub fn call(&self, args: A, recorded: Option<A>, fallback: impl FnOnce(A) -> R) -> R {
+        let mut state = self.state();
+        state.calls.push(recorded);
+
+        if !state.expectations.is_empty() {
+            let mut matched_any = false;
+            let mut accepted = false;
+            for expectation in state.expectations.iter_mut() {
+                if (expectation.matcher)(&args) {
+                    matched_any = true;
+                    if !expectation.saturated() {
+                        expectation.matched += 1;
+                        accepted = true;
+                        break;
+                    }
+                }
+            }
+
+            if !accepted {
+                drop(state);
+                if matched_any {
+                    panic!("`{}` called more times than expected", self.name);
+                } else {
+                    panic!("unexpected call to `{}`", self.name);
+                }
+            }
+        }
+
+        // Do not hold the lock while calling the closure, which may call
+        // the mocked function recursively.
+        let behavior = state.behavior.take();
+        drop(state);
+
+        match behavior {
+            Some(mut behavior) => {
+                let result = behavior(args);
+                self.state().behavior.get_or_insert(behavior);
+                result
+            }
+            None => fallback(args),
+        }
+    }
+}
+
+/// A handle to configure an expectation created by [`MockFn::expect`].
+pub struct ExpectationBuilder<'a, A, R> {
+    mock: &'a MockFn<A, R>,
+    index: usize,
+}
+
+impl<A, R> ExpectationBuilder<'_, A, R> {
+    /// Expect exactly `n` matching calls.
+    pub fn times(self, n: usize) -> Self {
+        self.mock.state().expectations[self.index].times = Times::Exactly(n);
+        self
+    }
+
+    /// Expect no matching calls.
+    pub fn never(self) -> Self {
+        self.times(0)
+    }
+}
+
+/// Wrapper of the arguments of a call to record them if possible.
+///
+/// Generated mocks call `(&Recorder(&args)).record()`, which resolves to
+/// [`RecordClone::record`] if the arguments implement [`Clone`], and
+/// [`RecordNone::record`] otherwise.
+#[doc(hidden)]
+pub struct Recorder<'a, T>(pub &'a T);
+
+#[doc(hidden)]
+pub trait RecordClone<T> {
+    fn record(&self) -> Option<T>;
+}
+
+impl<T: Clone> RecordClone<T> for Recorder<'_, T> {
+    fn record(&self) -> Option<T> {
+        Some(self.0.clone())
+    }
+}
+
+#[doc(hidden)]
+pub trait RecordNone<T> {
+    fn record(&self) -> Option<T>;
+}
+
+impl<T> RecordNone<T> for &Recorder<'_, T> {
+    fn record(&self) -> Option<T> {
+        None
+    }
+}
+
+/// The return value of a mocked function without a closure or a default
+/// body.
+///
+/// Generated mocks call `(&Unmocked::new(name)).value()`, which resolves to
+/// [`UnmockedDefault::value`] if the return type implements [`Default`], and
+/// [`UnmockedPanic::value`] otherwise.
+#[doc(hidden)]
+pub struct Unmocked<T>(&'static str, PhantomData<T>);
+
+impl<T> Unmocked<T> {
+    pub const fn new(name: &'static str) -> Self {
+        Self(name, PhantomData)
+    }
+}
+
+#[doc(hidden)]
+pub trait UnmockedDefault<T> {
+    fn value(&self) -> T;
+}
+
+impl<T: Default> UnmockedDefault<T> for Unmocked<T> {
+    fn value(&self) -> T {
+        T::default()
+    }
+}
+
+#[doc(hidden)]
+pub trait UnmockedPanic<T> {
+    fn value(&self) -> T;
+}
+
+impl<T> UnmockedPanic<T> for &Unmocked<T> {
+    fn value(&self) -> T {
+        panic!("no behavior set for mocked function `{}`", self.0)
+    }
+}
@@ -0,0 +1,184 @@
+// Copyright 2025 The Axvisor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Call statistics of the AxVisor APIs.
+//!
+//! This module is only available with the `stats` feature. With it, the
+//! caller of every API function defined with `#[api_def(stats)]` counts the
+//! calls to the function and measures their latency in ticks of
+//! [`time::current_ticks`](crate::time::current_ticks). All API traits of
+//! this crate have statistics.
+//!
+//! The counters are lock-free, so they can be updated from any context. Use
+//! [`snapshot`] to read them:
+//!
+//! ```rust,ignore
+//! for stats in axvisor_api::stats::snapshot() {
+//!     println!(
+//!         "{}: {} calls, {} ticks in total, {} ticks at most",
+//!         stats.api, stats.calls, stats.total_ticks, stats.max_ticks
+//!     );
+//! }
+//! ```
+//!
+//! An API function appears in the snapshot once it has been called.
+//!
+//! # Implementation
+//!
+//! The latency is measured with
+//! [`time::untraced::current_ticks`](crate::time::untraced::current_ticks),
+//! which bypasses the instrumentation, so the calls made for measuring are not
+//! counted themselves. Therefore, the `stats` feature requires `TimeIf` to be
+//! implemented.
+
+use core::{
+    ptr,
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, Ordering},
+};
+
+use crate::{ApiFn, time::Ticks};
+
+/// The statistics of an API function, a snapshot of its counters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ApiFnStats {
+    /// The API function.
+    pub api: ApiFn,
+    /// The number of completed calls.
+    pub calls: u64,
+    /// The cumulative latency of the calls, in ticks.
+    pub total_ticks: Ticks,
+    /// The maximum latency of a call, in ticks.
+    pub max_ticks: Ticks,
+}
+
+/// The counters of an API function, a static of which is generated in each
+/// caller.
+#[doc(hidden)]
+pub struct ApiFnCounters {
+    api: ApiFn,
+    calls: AtomicU64,
+    total_ticks: AtomicU64,
+    max_ticks: AtomicU64,
+    registered: AtomicBool,
+    next: AtomicPtr<ApiFnCounters>,
+}
+
+/// The head of the intrusive list of the counters of all called API
+/// functions.
+static HEAD: AtomicPtr<ApiFnCounters> = AtomicPtr::new(ptr::null_mut());
+
+impl ApiFnCounters {
+    /// Create the counters of an API function.
+    pub const fn new(api: ApiFn) -> Self {
+        Self {
+            api,
+            calls: AtomicU64::new(0),
+            total_ticks: AtomicU64::new(0),
+            max_ticks: AtomicU64::new(0),
+            registered: AtomicBool::new(false),
+            next: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    /// Start measuring a call, returning the current ticks.
+    #[inline]
+    pub fn start(&'static self) -> Ticks {
+        now()
+    }
+
+    /// Record a call started at `start`.
+    pub fn record(&'static self, start: Ticks) {
+        let ticks = now().wrapping_sub(start);
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        self.total_ticks.fetch_add(ticks, Ordering::Relaxed);
+        self.max_ticks.fetch_max(ticks, Ordering::Relaxed);
+        self.register();
+    }
+
+    /// Add the counters to the list on the first call.
+    fn register(&'static self) {
+        if self.registered.load(Ordering::Relaxed) || self.registered.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        let this = self as *const Self as *mut Self;
+        let mut head = HEAD.load(Ordering::Relaxed);
+        loop {
+            self.next.store(head, Ordering::Relaxed);
+            match HEAD.compare_exchange_weak(head, this, Ordering::Release, Ordering::Relaxed) {
+                Ok(_) => break,
+                Err(current) => head = current,
+            }
+        }
+    }
+
+    fn snapshot(&self) -> ApiFnStats {
+        ApiFnStats {
+            api: self.api,
+            calls: self.calls.load(Ordering::Relaxed),
+            total_ticks: self.total_ticks.load(Ordering::Relaxed),
+            max_ticks: self.max_ticks.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Get the current ticks, bypassing the instrumentation of
+/// `TimeIf::current_ticks` so that measuring is not measured.
+fn now() -> Ticks {
+    crate::time::untraced::current_ticks()
+}
+
+/// An iterator over the statistics of the called API functions, returned by
+/// [`snapshot`].
+pub struct Snapshot {
+    next: *const ApiFnCounters,
+}
+
+impl Iterator for Snapshot {
+    type Item = ApiFnStats;
+
+    fn next(&mut self) -> Option<ApiFnStats> {
+        // SAFETY: the list only contains `'static` counters.
+        let counters = unsafe { self.next.as_ref()? };
+        self.next = counters.next.load(Ordering::Relaxed);
+        Some(counters.snapshot())
+    }
+}
+
+/// Get the statistics of all API functions called so far.
+///
+/// The counters of each function are read individually, so a snapshot taken
+/// while the APIs are being called may be slightly inconsistent.
+pub fn snapshot() -> Snapshot {
+    Snapshot {
+        next: HEAD.load(Ordering::Acquire),
+    }
+}
+
+/// Get the statistics of an API function, if it has been called.
+pub fn get(trait_name: &str, fn_name: &str) -> Option<ApiFnStats> {
+    snapshot().find(|stats| stats.api.trait_name == trait_name && stats.api.fn_name == fn_name)
+}
+
+/// Reset the counters of all API functions to zero.
+pub fn reset() {
+    let mut next = HEAD.load(Ordering::Acquire);
+    // SAFETY: the list only contains `'static` counters.
+    while let Some(counters) = unsafe { next.as_ref() } {
+        counters.calls.store(0, Ordering::Relaxed);
+        counters.total_ticks.store(0, Ordering::Relaxed);
+        counters.max_ticks.store(0, Ordering::Relaxed);
+        next = counters.next.load(Ordering::Relaxed);
+    }
+}
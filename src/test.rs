@@ -12,53 +12,160 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+extern crate std;
+
 use memory_addr::{pa, va};
 
 mod memory_impl {
     extern crate std; // in test only
 
+    use crate::memory::{
+        HostMemoryFlags, HostMemoryKind, HostMemoryRegion, HostMemoryRegions, MemAttr,
+    };
+    use core::cell::UnsafeCell;
     use memory_addr::{PhysAddr, VirtAddr, pa, va};
     use std::sync::{
         Mutex, MutexGuard,
-        atomic::{AtomicUsize, Ordering},
+        atomic::{AtomicBool, AtomicUsize, Ordering},
     };
 
     static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
     static RETURNED_SUM: AtomicUsize = AtomicUsize::new(0);
     static LOCK: Mutex<()> = Mutex::new(());
     pub const VA_PA_OFFSET: usize = 0x1000;
+    pub const RAM_SIZE: usize = 0x10_0000;
+
+    /// The fake physical address of the arena, which backs the frames whose
+    /// contents are accessed by the tests.
+    pub const ARENA_PA: usize = 0x4000_0000;
+    /// The size of the arena, enough for a 2 MiB page at any alignment.
+    pub const ARENA_SIZE: usize = 0x40_0000;
+    const ARENA_FRAMES: usize = ARENA_SIZE / 0x1000;
+
+    #[repr(C, align(0x20_0000))]
+    struct Arena(UnsafeCell<[u8; ARENA_SIZE]>);
+
+    // SAFETY: the frames of the arena are only accessed by their owners.
+    unsafe impl Sync for Arena {}
+
+    static ARENA: Arena = Arena(UnsafeCell::new([0; ARENA_SIZE]));
+    static ARENA_USED: Mutex<[bool; ARENA_FRAMES]> = Mutex::new([false; ARENA_FRAMES]);
+    static ARENA_MODE: AtomicBool = AtomicBool::new(false);
+
+    fn arena_offset(addr: PhysAddr) -> Option<usize> {
+        let offset = addr.as_usize().checked_sub(ARENA_PA)?;
+        (offset < ARENA_SIZE).then_some(offset)
+    }
+
+    fn arena_va_offset(addr: VirtAddr) -> Option<usize> {
+        let offset = addr.as_usize().checked_sub(ARENA.0.get() as usize)?;
+        (offset < ARENA_SIZE).then_some(offset)
+    }
+
+    /// Allocate frames from the arena first-fit, filling them with garbage
+    /// so that missing initialization shows up in the tests.
+    fn arena_alloc(num_frames: usize, frame_align_pow2: usize) -> Option<PhysAddr> {
+        let align = 1usize.checked_shl(frame_align_pow2 as u32)?;
+        let mut used = ARENA_USED.lock().unwrap();
+        let start = (0..ARENA_FRAMES).step_by(align).find(|&start| {
+            start + num_frames <= ARENA_FRAMES && used[start..start + num_frames].iter().all(|u| !u)
+        })?;
+        used[start..start + num_frames].fill(true);
+        let vaddr = ARENA.0.get() as usize + start * 0x1000;
+        unsafe { core::ptr::write_bytes(vaddr as *mut u8, 0xa5, num_frames * 0x1000) };
+        Some(pa!(ARENA_PA + start * 0x1000))
+    }
+
+    fn arena_dealloc(addr: PhysAddr, num_frames: usize) {
+        let start = arena_offset(addr).expect("frames not from the arena") / 0x1000;
+        let mut used = ARENA_USED.lock().unwrap();
+        let frames = &mut used[start..start + num_frames];
+        assert!(frames.iter().all(|&u| u), "frames freed twice");
+        frames.fill(false);
+    }
 
     pub struct MemoryIfImpl;
 
     #[crate::api_impl]
     impl crate::memory::MemoryIf for MemoryIfImpl {
         fn alloc_frame() -> Option<PhysAddr> {
+            if ARENA_MODE.load(Ordering::Relaxed) {
+                return arena_alloc(1, 0);
+            }
+
             let value = ALLOCATED.fetch_add(1, Ordering::Relaxed);
 
             Some(pa!(value * 0x1000))
         }
 
-        fn alloc_contiguous_frames(
-            _num_frames: usize,
-            _frame_align_pow2: usize,
-        ) -> Option<PhysAddr> {
-            unimplemented!();
+        fn alloc_contiguous_frames(num_frames: usize, frame_align_pow2: usize) -> Option<PhysAddr> {
+            arena_alloc(num_frames, frame_align_pow2)
         }
 
-        fn dealloc_frame(addr: PhysAddr) {
+        unsafe fn dealloc_frame(addr: PhysAddr) {
+            if arena_offset(addr).is_some() {
+                arena_dealloc(addr, 1);
+            }
             RETURNED_SUM.fetch_add(addr.as_usize(), Ordering::Relaxed);
         }
 
-        fn dealloc_contiguous_frames(_first_addr: PhysAddr, _num_frames: usize) {
-            unimplemented!();
+        unsafe fn dealloc_contiguous_frames(first_addr: PhysAddr, num_frames: usize) {
+            arena_dealloc(first_addr, num_frames);
         }
 
         fn phys_to_virt(addr: PhysAddr) -> VirtAddr {
-            va!(addr.as_usize() + VA_PA_OFFSET) // Example implementation
+            match arena_offset(addr) {
+                Some(offset) => va!(ARENA.0.get() as usize + offset),
+                None => va!(addr.as_usize() + VA_PA_OFFSET), // Example implementation
+            }
         }
 
         fn virt_to_phys(addr: VirtAddr) -> PhysAddr {
-            pa!(addr.as_usize() - VA_PA_OFFSET) // Example implementation
+            match arena_va_offset(addr) {
+                Some(offset) => pa!(ARENA_PA + offset),
+                None => pa!(addr.as_usize() - VA_PA_OFFSET), // Example implementation
+            }
+        }
+
+        fn try_phys_to_virt(addr: PhysAddr) -> Option<VirtAddr> {
+            if arena_offset(addr).is_some() {
+                return Some(Self::phys_to_virt(addr));
+            }
+            // Example implementation
+            (addr.as_usize() < RAM_SIZE).then(|| va!(addr.as_usize() + VA_PA_OFFSET))
+        }
+
+        fn try_virt_to_phys(addr: VirtAddr) -> Option<PhysAddr> {
+            if arena_va_offset(addr).is_some() {
+                return Some(Self::virt_to_phys(addr));
+            }
+            // Example implementation
+            let paddr = addr.as_usize().checked_sub(VA_PA_OFFSET)?;
+            (paddr < RAM_SIZE).then(|| pa!(paddr))
+        }
+
+        fn map_mmio(paddr: PhysAddr, size: usize, _attr: MemAttr) -> Option<VirtAddr> {
+            // Device memory is mapped in the linear mapping with device
+            // attributes at boot.
+            Self::try_phys_to_virt_range(paddr, size)
+        }
+
+        fn host_memory_regions() -> HostMemoryRegions {
+            static REGIONS: [HostMemoryRegion; 2] = [
+                HostMemoryRegion {
+                    paddr: pa!(0),
+                    size: RAM_SIZE,
+                    kind: HostMemoryKind::Ram,
+                    flags: HostMemoryFlags::MAPPED.union(HostMemoryFlags::ALLOCATABLE),
+                },
+                HostMemoryRegion {
+                    paddr: pa!(0x900_0000),
+                    size: 0x1000,
+                    kind: HostMemoryKind::Mmio,
+                    flags: HostMemoryFlags::PASSTHROUGH,
+                },
+            ];
+            HostMemoryRegions::new(&REGIONS)
         }
     }
 
@@ -69,15 +176,209 @@ mod memory_impl {
         RETURNED_SUM.load(Ordering::Relaxed)
     }
 
+    /// Get the number of free frames in the arena.
+    pub fn arena_free_frames() -> usize {
+        ARENA_USED.lock().unwrap().iter().filter(|&&u| !u).count()
+    }
+
     /// Start a test by acquiring the lock and resetting the internal state.
     pub fn enter_test() -> MutexGuard<'static, ()> {
         let guard = LOCK.lock().unwrap();
         ALLOCATED.store(0, Ordering::Relaxed);
         RETURNED_SUM.store(0, Ordering::Relaxed);
+        ARENA_MODE.store(false, Ordering::Relaxed);
+        ARENA_USED.lock().unwrap().fill(false);
+        guard
+    }
+
+    /// Start a test whose single frames are allocated from the arena too, so
+    /// that their contents can be accessed.
+    pub fn enter_arena_test() -> MutexGuard<'static, ()> {
+        let guard = enter_test();
+        ARENA_MODE.store(true, Ordering::Relaxed);
         guard
     }
 }
 
+mod vmm_impl {
+    extern crate std; // in test only
+
+    use crate::{
+        memory::accounting,
+        vmm::{
+            DirtyBitmap, DirtyLogError, GuestAccess, GuestMemoryError, InterruptVector, VCpuId,
+            VMId, dirty_log::SoftDirtyLog,
+        },
+    };
+    use axaddrspace::GuestPhysAddr;
+    use memory_addr::{MemoryAddr, PhysAddr};
+    use std::{
+        collections::BTreeMap,
+        sync::{
+            Mutex,
+            atomic::{AtomicUsize, Ordering},
+        },
+    };
+
+    /// The number of VMs, whose IDs are from 0 to `VM_NUM` (exclusive).
+    pub const VM_NUM: usize = 16;
+
+    static CURRENT_VM: AtomicUsize = AtomicUsize::new(0);
+    /// The software stage-2 mappings, by VM and guest page number.
+    static STAGE2: Mutex<BTreeMap<(VMId, usize), Mapping>> = Mutex::new(BTreeMap::new());
+    static DIRTY_LOG: SoftDirtyLog = SoftDirtyLog::new();
+
+    /// A guest page mapping.
+    #[derive(Clone, Copy)]
+    struct Mapping {
+        hpa: PhysAddr,
+        writable: bool,
+        /// Whether the frame is owned by the mapping, and deallocated when
+        /// unmapped, or a part of memory owned by the test.
+        owned: bool,
+    }
+
+    fn map(vm_id: VMId, gpa: GuestPhysAddr, mapping: Mapping) {
+        assert!(gpa.is_aligned_4k() && mapping.hpa.is_aligned_4k());
+        let old = STAGE2
+            .lock()
+            .unwrap()
+            .insert((vm_id, gpa.as_usize() >> 12), mapping);
+        assert!(old.is_none(), "guest page mapped twice");
+    }
+
+    pub struct VmmIfImpl;
+
+    #[crate::api_impl]
+    impl crate::vmm::VmmIf for VmmIfImpl {
+        fn current_vm_id() -> VMId {
+            CURRENT_VM.load(Ordering::Relaxed)
+        }
+
+        fn current_vcpu_id() -> VCpuId {
+            0
+        }
+
+        fn vcpu_num(_vm_id: VMId) -> Option<usize> {
+            Some(1)
+        }
+
+        fn active_vcpus(_vm_id: VMId) -> Option<usize> {
+            Some(1)
+        }
+
+        fn inject_interrupt(_vm_id: VMId, _vcpu_id: VCpuId, _vector: InterruptVector) {}
+
+        fn notify_vcpu_timer_expired(_vm_id: VMId, _vcpu_id: VCpuId) {}
+
+        fn translate_guest_phys(
+            vm_id: VMId,
+            gpa: GuestPhysAddr,
+            access: GuestAccess,
+        ) -> Result<PhysAddr, GuestMemoryError> {
+            if vm_id >= VM_NUM {
+                return Err(GuestMemoryError::InvalidVm);
+            }
+            let stage2 = STAGE2.lock().unwrap();
+            let mapping = stage2
+                .get(&(vm_id, gpa.as_usize() >> 12))
+                .ok_or(GuestMemoryError::NotMapped(gpa))?;
+            if access == GuestAccess::Write {
+                if !mapping.writable {
+                    return Err(GuestMemoryError::PermissionDenied(gpa));
+                }
+                DIRTY_LOG.mark_dirty(vm_id, gpa, 1);
+            }
+            Ok(mapping.hpa + gpa.align_offset_4k())
+        }
+
+        fn map_guest_page(
+            vm_id: VMId,
+            gpa: GuestPhysAddr,
+            hpa: PhysAddr,
+        ) -> Result<(), GuestMemoryError> {
+            if vm_id >= VM_NUM {
+                return Err(GuestMemoryError::InvalidVm);
+            }
+            let mapping = Mapping {
+                hpa,
+                writable: true,
+                owned: true,
+            };
+            map(vm_id, gpa, mapping);
+            Ok(())
+        }
+
+        fn unmap_guest_page(vm_id: VMId, gpa: GuestPhysAddr) -> Result<(), GuestMemoryError> {
+            if vm_id >= VM_NUM {
+                return Err(GuestMemoryError::InvalidVm);
+            }
+            let mapping = STAGE2
+                .lock()
+                .unwrap()
+                .remove(&(vm_id, gpa.as_usize() >> 12))
+                .ok_or(GuestMemoryError::NotMapped(gpa))?;
+            if mapping.owned {
+                // SAFETY: the frame was allocated for the VM, and owned by
+                // the mapping.
+                unsafe { accounting::dealloc_frame_for(vm_id, mapping.hpa) };
+            }
+            Ok(())
+        }
+
+        fn enable_dirty_log(vm_id: VMId) -> Result<(), DirtyLogError> {
+            DIRTY_LOG.enable(vm_id)
+        }
+
+        fn disable_dirty_log(vm_id: VMId) -> Result<(), DirtyLogError> {
+            DIRTY_LOG.disable(vm_id)
+        }
+
+        fn fetch_and_clear_dirty_log(
+            vm_id: VMId,
+            gpa: GuestPhysAddr,
+            num_pages: usize,
+        ) -> Result<DirtyBitmap, DirtyLogError> {
+            DIRTY_LOG.fetch_and_clear(vm_id, gpa, num_pages)
+        }
+    }
+
+    /// Set the VM reported by `current_vm_id`.
+    pub fn set_current_vm(vm_id: VMId) {
+        CURRENT_VM.store(vm_id, Ordering::Relaxed);
+    }
+
+    /// Map host memory owned by the test at guest physical pages of a VM.
+    pub fn map_guest_memory(
+        vm_id: VMId,
+        gpa: GuestPhysAddr,
+        hpa: PhysAddr,
+        size: usize,
+        writable: bool,
+    ) {
+        for offset in (0..size).step_by(0x1000) {
+            let mapping = Mapping {
+                hpa: hpa + offset,
+                writable,
+                owned: false,
+            };
+            map(vm_id, gpa + offset, mapping);
+        }
+    }
+}
+
+mod host_impl {
+    pub struct HostIfImpl;
+
+    /// A host without NUMA support, relying on the default implementations.
+    #[crate::api_impl]
+    impl crate::host::HostIf for HostIfImpl {
+        fn get_host_cpu_num() -> usize {
+            4
+        }
+    }
+}
+
 #[test]
 pub fn test_memory() {
     use crate::memory;
@@ -92,11 +393,11 @@ pub fn test_memory() {
     assert_eq!(frame2, Some(pa!(0x1000)));
     assert_eq!(frame3, Some(pa!(0x2000)));
 
-    memory::dealloc_frame(frame2.unwrap());
+    unsafe { memory::dealloc_frame(frame2.unwrap()) };
     assert_eq!(memory_impl::get_returned_sum(), 0x1000);
-    memory::dealloc_frame(frame3.unwrap());
+    unsafe { memory::dealloc_frame(frame3.unwrap()) };
     assert_eq!(memory_impl::get_returned_sum(), 0x3000);
-    memory::dealloc_frame(frame1.unwrap());
+    unsafe { memory::dealloc_frame(frame1.unwrap()) };
     assert_eq!(memory_impl::get_returned_sum(), 0x3000);
 
     assert_eq!(memory::phys_to_virt(pa!(0)), va!(memory_impl::VA_PA_OFFSET));
@@ -105,13 +406,75 @@ pub fn test_memory() {
     drop(guard);
 }
 
+#[test]
+pub fn test_host_memory_regions() {
+    use crate::memory::{self, HostMemoryFlags, HostMemoryKind};
+
+    let regions = memory::host_memory_regions();
+    assert_eq!(regions.len(), 2);
+    assert_eq!(
+        regions
+            .map(|region| region.kind)
+            .collect::<std::vec::Vec<_>>(),
+        [HostMemoryKind::Ram, HostMemoryKind::Mmio]
+    );
+
+    let passthrough = |addr, size| {
+        memory::host_memory_regions().any(|region| {
+            region.flags.contains(HostMemoryFlags::PASSTHROUGH) && region.contains_range(addr, size)
+        })
+    };
+    assert!(passthrough(pa!(0x900_0000), 0x1000));
+    assert!(!passthrough(pa!(0x900_0000), 0x1001));
+    assert!(!passthrough(pa!(0x1000), 0x1000));
+}
+
+#[test]
+pub fn test_ioremap_linear() {
+    use crate::memory::{self, MemAttr};
+
+    let region = memory::ioremap(pa!(0x2000), 0x1000, MemAttr::Device).unwrap();
+    assert_eq!(region.vaddr(), va!(0x3000));
+    memory::iounmap(region);
+    assert!(memory::ioremap(pa!(memory_impl::RAM_SIZE), 0x1000, MemAttr::Device).is_none());
+}
+
+#[test]
+pub fn test_memory_try_translate() {
+    use crate::memory;
+    use memory_impl::{RAM_SIZE, VA_PA_OFFSET};
+
+    assert_eq!(memory::try_phys_to_virt(pa!(0x2000)), Some(va!(0x3000)));
+    assert_eq!(memory::try_phys_to_virt(pa!(RAM_SIZE)), None);
+    assert_eq!(memory::try_virt_to_phys(va!(0)), None);
+
+    assert_eq!(
+        memory::try_phys_to_virt_range(pa!(RAM_SIZE - 0x1000), 0x1000),
+        Some(va!(RAM_SIZE - 0x1000 + VA_PA_OFFSET))
+    );
+    assert_eq!(
+        memory::try_phys_to_virt_range(pa!(RAM_SIZE - 0x1000), 0x1001),
+        None
+    );
+    assert_eq!(
+        memory::try_phys_to_virt_range(pa!(0x1000), usize::MAX),
+        None
+    );
+    assert_eq!(
+        memory::try_virt_to_phys_range(va!(VA_PA_OFFSET), RAM_SIZE),
+        Some(pa!(0))
+    );
+    assert_eq!(memory::try_virt_to_phys_range(va!(0), 0), None);
+}
+
 #[test]
 pub fn test_memory_phys_frame() {
-    use crate::memory::{self, PhysFrame};
+    use crate::memory::{self, PhysFrame, accounting};
 
     let guard = memory_impl::enter_test();
 
     let _ = memory::alloc_frame();
+    vmm_impl::set_current_vm(5);
     let frame1 = PhysFrame::alloc().unwrap();
     let frame2 = PhysFrame::alloc().unwrap();
     let frame3 = PhysFrame::alloc().unwrap();
@@ -119,6 +482,8 @@ pub fn test_memory_phys_frame() {
     assert_eq!(frame1.start_paddr(), pa!(0x1000));
     assert_eq!(frame2.start_paddr(), pa!(0x2000));
     assert_eq!(frame3.start_paddr(), pa!(0x3000));
+    // page table frames are not charged to any VM
+    assert_eq!(accounting::usage(5).frames, 0);
 
     drop(frame2);
     assert_eq!(memory_impl::get_returned_sum(), 0x2000);
@@ -126,6 +491,1199 @@ pub fn test_memory_phys_frame() {
     assert_eq!(memory_impl::get_returned_sum(), 0x5000);
     drop(frame1);
     assert_eq!(memory_impl::get_returned_sum(), 0x6000);
+    vmm_impl::set_current_vm(0);
+
+    drop(guard);
+}
+
+#[test]
+pub fn test_memory_alloc_error() {
+    use crate::memory::{self, AllocError};
+
+    let guard = memory_impl::enter_test();
+
+    assert_eq!(memory::try_alloc_frame(), Ok(pa!(0x0)));
+    assert_eq!(
+        memory::try_alloc_contiguous_frames(0, 0),
+        Err(AllocError::InvalidParam)
+    );
+    assert_eq!(
+        memory::try_alloc_contiguous_frames(1, 64),
+        Err(AllocError::UnsupportedAlignment)
+    );
+    assert_eq!(
+        memory::try_alloc_contiguous_frames(1, 60),
+        Err(AllocError::UnsupportedAlignment)
+    );
+    assert_eq!(
+        memory::try_alloc_contiguous_frames(1, usize::BITS as usize - 12),
+        Err(AllocError::UnsupportedAlignment)
+    );
+    assert_eq!(
+        std::format!("{}", AllocError::QuotaExceeded),
+        "memory quota exceeded"
+    );
+
+    drop(guard);
+}
+
+#[test]
+pub fn test_memory_phys_frame_range() {
+    use crate::memory::{self, PhysFrameRange, accounting};
+    use memory_impl::{ARENA_PA, ARENA_SIZE};
+
+    let guard = memory_impl::enter_test();
+    let total_frames = ARENA_SIZE / 0x1000;
+
+    let mut frames = PhysFrameRange::alloc_for(6, 2, 1).unwrap();
+    assert_eq!(frames.start_paddr(), pa!(ARENA_PA));
+    assert_eq!(frames.end_paddr(), pa!(ARENA_PA + 0x2000));
+    assert_eq!(frames.owner(), 6);
+    assert_eq!(accounting::usage(6).frames, 2);
+
+    frames.fill(0);
+    frames.as_mut_slice()[0x1000] = 0x42;
+    assert!(frames.as_slice()[..0x1000].iter().all(|&b| b == 0));
+    assert_eq!(frames.as_slice()[0x1000], 0x42);
+    assert_eq!(
+        memory::virt_to_phys(va!(frames.as_ptr() as usize + 0x1000)),
+        pa!(ARENA_PA + 0x1000)
+    );
+
+    // the alignment is honored past the allocated frames
+    let aligned = PhysFrameRange::alloc_for(6, 1, 2).unwrap();
+    assert_eq!(aligned.start_paddr(), pa!(ARENA_PA + 0x4000));
+    assert_eq!(memory_impl::arena_free_frames(), total_frames - 3);
+
+    drop(frames);
+    assert_eq!(accounting::usage(6).frames, 1);
+    let (owner, start_paddr, num_frames, frame_align_pow2) = aligned.into_raw_parts();
+    assert_eq!(memory_impl::arena_free_frames(), total_frames - 1);
+    drop(unsafe {
+        PhysFrameRange::from_raw_parts(owner, start_paddr, num_frames, frame_align_pow2)
+    });
+    assert_eq!(memory_impl::arena_free_frames(), total_frames);
+    assert_eq!(accounting::remove(6).unwrap().frames, 0);
 
     drop(guard);
 }
+
+#[test]
+pub fn test_memory_sized_phys_frame() {
+    use crate::memory::{AllocError, PageSize, SizedPhysFrame, accounting};
+    use memory_impl::{ARENA_PA, ARENA_SIZE};
+
+    let guard = memory_impl::enter_test();
+    let total_frames = ARENA_SIZE / 0x1000;
+
+    let small = SizedPhysFrame::alloc_for(7, PageSize::Size4K).unwrap();
+    assert_eq!(small.start_paddr(), pa!(ARENA_PA));
+
+    // huge pages are aligned to their size
+    let mut huge = SizedPhysFrame::alloc_for(7, PageSize::Size2M).unwrap();
+    assert_eq!(huge.start_paddr(), pa!(ARENA_PA + 0x20_0000));
+    assert_eq!(huge.size(), 0x20_0000);
+    assert_eq!(accounting::usage(7).frames, 513);
+    assert_eq!(memory_impl::arena_free_frames(), total_frames - 513);
+
+    huge.fill(0x5a);
+    assert!(huge.as_slice().iter().all(|&b| b == 0x5a));
+
+    assert_eq!(
+        SizedPhysFrame::alloc_for(7, PageSize::Size1G).unwrap_err(),
+        AllocError::NoMemory
+    );
+    assert_eq!(accounting::usage(7).frames, 513);
+
+    drop(huge);
+    drop(small);
+    assert_eq!(memory_impl::arena_free_frames(), total_frames);
+    assert_eq!(accounting::remove(7).unwrap().frames, 0);
+
+    drop(guard);
+}
+
+#[test]
+pub fn test_memory_alloc_zeroed() {
+    use crate::memory;
+    use memory_impl::ARENA_PA;
+
+    let guard = memory_impl::enter_arena_test();
+
+    // the arena hands out frames filled with garbage
+    let dirty = memory::alloc_contiguous_frames(3, 0).unwrap();
+    let contents = |addr, len| unsafe {
+        core::slice::from_raw_parts(memory::phys_to_virt(addr).as_ptr(), len)
+    };
+    assert!(contents(dirty, 0x3000).iter().all(|&b| b == 0xa5));
+    unsafe { memory::dealloc_contiguous_frames(dirty, 3) };
+
+    let frames = memory::alloc_zeroed_contiguous_frames(2, 0).unwrap();
+    assert_eq!(frames, pa!(ARENA_PA));
+    assert!(contents(frames, 0x2000).iter().all(|&b| b == 0));
+
+    let frame = memory::alloc_zeroed_frame().unwrap();
+    assert_eq!(frame, pa!(ARENA_PA + 0x2000));
+    assert!(contents(frame, 0x1000).iter().all(|&b| b == 0));
+
+    unsafe {
+        memory::dealloc_frame(frame);
+        memory::dealloc_contiguous_frames(frames, 2);
+    }
+    drop(guard);
+}
+
+#[test]
+pub fn test_memory_placement_hint() {
+    use crate::{
+        host,
+        memory::{self, PlacementHint},
+    };
+    use memory_impl::ARENA_PA;
+
+    let guard = memory_impl::enter_test();
+
+    assert_eq!(
+        memory::alloc_frame_with_hint(PlacementHint::Dma32),
+        Ok(pa!(0x0))
+    );
+    // the preferred node is ignored without NUMA support
+    assert_eq!(
+        memory::alloc_frame_with_hint(PlacementHint::NearCpu(3)),
+        Ok(pa!(0x1000))
+    );
+    let frames = memory::alloc_contiguous_frames_with_hint(2, 1, PlacementHint::Node(1)).unwrap();
+    assert_eq!(frames, pa!(ARENA_PA));
+    unsafe { memory::dealloc_contiguous_frames(frames, 2) };
+
+    assert!(PlacementHint::Dma32.is_satisfied_by(pa!(0xffff_f000), 0x1000));
+    assert!(!PlacementHint::Dma32.is_satisfied_by(pa!(0xffff_f000), 0x2000));
+    assert!(!PlacementHint::Dma32.is_satisfied_by(pa!(usize::MAX), 0x1000));
+    assert!(PlacementHint::Node(1).is_satisfied_by(pa!(0xffff_f000), 0x2000));
+
+    // hosts without NUMA support have a single node
+    assert_eq!(host::get_host_cpu_num(), 4);
+    assert_eq!(host::get_host_numa_node_num(), 1);
+    assert_eq!(host::get_host_cpu_numa_node(3), 0);
+    assert_eq!(host::get_host_numa_node_free_memory(0), None);
+
+    drop(guard);
+}
+
+#[test]
+pub fn test_memory_accounting() {
+    use crate::memory::{AllocError, PageSize, accounting};
+    use memory_impl::{ARENA_PA, ARENA_SIZE};
+
+    let guard = memory_impl::enter_arena_test();
+    let total_frames = ARENA_SIZE / 0x1000;
+
+    vmm_impl::set_current_vm(9);
+    accounting::set_limit(9, Some(4));
+    let frame = accounting::alloc_zeroed_frame().unwrap();
+    assert_eq!(frame, pa!(ARENA_PA));
+    let frames = accounting::alloc_contiguous_frames_for(9, 3, 0).unwrap();
+    assert_eq!(
+        accounting::alloc_frame_for(9),
+        Err(AllocError::QuotaExceeded)
+    );
+    // the quota is checked before allocating
+    assert_eq!(
+        accounting::alloc_frames_of_size_for(9, PageSize::Size2M),
+        Err(AllocError::QuotaExceeded)
+    );
+    assert_eq!(memory_impl::arena_free_frames(), total_frames - 4);
+
+    // failed allocations are not charged
+    accounting::set_limit(9, None);
+    assert_eq!(
+        accounting::alloc_frames_of_size_for(9, PageSize::Size1G),
+        Err(AllocError::NoMemory)
+    );
+    assert_eq!(accounting::usage(9).frames, 4);
+
+    let huge = accounting::alloc_frames_of_size(PageSize::Size2M).unwrap();
+    assert_eq!(accounting::usage(9).frames, 516);
+    unsafe {
+        accounting::dealloc_frames_of_size(huge, PageSize::Size2M);
+        accounting::dealloc_contiguous_frames(frames, 3);
+        accounting::dealloc_frame(frame);
+    }
+    assert_eq!(memory_impl::arena_free_frames(), total_frames);
+
+    let usage = accounting::remove(9).unwrap();
+    assert_eq!((usage.frames, usage.peak_frames), (0, 516));
+    vmm_impl::set_current_vm(0);
+
+    drop(guard);
+}
+
+#[test]
+pub fn test_guest_memory() {
+    use crate::{
+        memory::{self, accounting},
+        vmm::{self, GuestAccess, GuestMemoryError},
+    };
+    use axaddrspace::GuestPhysAddr;
+
+    let guard = memory_impl::enter_arena_test();
+    let gpa = GuestPhysAddr::from_usize;
+
+    // guest pages 0x1000 (read-write) and 0x2000 (read-only) are backed by
+    // discontiguous host frames
+    let page0 = accounting::alloc_zeroed_frame_for(1).unwrap();
+    let hole = memory::alloc_frame().unwrap();
+    let page1 = memory::alloc_zeroed_frame().unwrap();
+    vmm::map_guest_page(1, gpa(0x1000), page0).unwrap();
+    vmm_impl::map_guest_memory(1, gpa(0x2000), page1, 0x1000, false);
+    assert_eq!(
+        vmm::translate_guest_phys(1, gpa(0x1234), GuestAccess::Write),
+        Ok(page0 + 0x234)
+    );
+    let page1_bytes = unsafe {
+        core::slice::from_raw_parts_mut(memory::phys_to_virt(page1).as_mut_ptr(), 0x1000)
+    };
+
+    vmm::write_guest_obj(1, gpa(0x1ffc), &0x1122_3344u32).unwrap();
+    page1_bytes[..4].copy_from_slice(&[0x55, 0x66, 0x77, 0x88]);
+    assert_eq!(
+        vmm::read_guest_obj::<u64>(1, gpa(0x1ffc)).unwrap(),
+        0x8877_6655_1122_3344
+    );
+    let mut buf = [0u8; 6];
+    vmm::read_guest_memory(1, gpa(0x1ffe), &mut buf).unwrap();
+    assert_eq!(buf, [0x22, 0x11, 0x55, 0x66, 0x77, 0x88]);
+
+    // the first page is written before the permission error on the second
+    assert_eq!(
+        vmm::write_guest_memory(1, gpa(0x1fff), &[0xaa, 0xbb]),
+        Err(GuestMemoryError::PermissionDenied(gpa(0x2000)))
+    );
+    assert_eq!(vmm::read_guest_obj::<u8>(1, gpa(0x1fff)), Ok(0xaa));
+    assert_eq!(page1_bytes[0], 0x55);
+    assert_eq!(
+        vmm::read_guest_obj::<u32>(1, gpa(0x2ffe)),
+        Err(GuestMemoryError::NotMapped(gpa(0x3000)))
+    );
+    assert_eq!(
+        vmm::read_guest_memory(vmm_impl::VM_NUM, gpa(0x1000), &mut buf),
+        Err(GuestMemoryError::InvalidVm)
+    );
+
+    // the frame mapped with `map_guest_page` is deallocated when unmapped
+    assert_eq!(vmm::unmap_guest_page(1, gpa(0x1000)), Ok(()));
+    assert_eq!(vmm::unmap_guest_page(1, gpa(0x2000)), Ok(()));
+    assert_eq!(
+        vmm::read_guest_obj::<u8>(1, gpa(0x1000)),
+        Err(GuestMemoryError::NotMapped(gpa(0x1000)))
+    );
+    assert_eq!(accounting::remove(1).unwrap().frames, 0);
+    unsafe {
+        memory::dealloc_frame(hole);
+        memory::dealloc_frame(page1);
+    }
+
+    drop(guard);
+}
+
+#[test]
+pub fn test_dirty_log() {
+    use crate::{
+        memory,
+        vmm::{self, DirtyBitmap, DirtyLogError},
+    };
+    use axaddrspace::GuestPhysAddr;
+    use std::vec::Vec;
+
+    let guard = memory_impl::enter_test();
+    let gpa = GuestPhysAddr::from_usize;
+
+    // guest writes through `write_guest_memory` are logged by the host
+    let ram = memory::alloc_zeroed_contiguous_frames(4, 0).unwrap();
+    vmm_impl::map_guest_memory(2, gpa(0x0), ram, 0x4000, true);
+
+    vmm::write_guest_memory(2, gpa(0x0), &[1]).unwrap();
+    vmm::enable_dirty_log(2).unwrap();
+    vmm::write_guest_memory(2, gpa(0xffe), &[1, 2, 3, 4]).unwrap();
+    vmm::write_guest_obj(2, gpa(0x3000), &0u64).unwrap();
+    // reads are not logged
+    vmm::read_guest_obj::<u64>(2, gpa(0x2000)).unwrap();
+
+    let dirty = vmm::fetch_and_clear_dirty_log(2, gpa(0x1000), 3).unwrap();
+    assert_eq!(
+        dirty.dirty_pages().collect::<Vec<_>>(),
+        [gpa(0x1000), gpa(0x3000)]
+    );
+    let dirty = vmm::fetch_and_clear_dirty_log(2, gpa(0x0), 4).unwrap();
+    assert_eq!(dirty.dirty_pages().collect::<Vec<_>>(), [gpa(0x0)]);
+    assert_eq!(
+        vmm::fetch_and_clear_dirty_log(2, gpa(0x0), 4),
+        Ok(DirtyBitmap::new(gpa(0x0), 4))
+    );
+
+    assert_eq!(
+        vmm::fetch_and_clear_dirty_log(2, gpa(0x800), 1),
+        Err(DirtyLogError::InvalidParam)
+    );
+    assert_eq!(
+        vmm::fetch_and_clear_dirty_log(3, gpa(0x0), 4),
+        Err(DirtyLogError::NotEnabled)
+    );
+    vmm::disable_dirty_log(2).unwrap();
+    assert_eq!(vmm::disable_dirty_log(2), Err(DirtyLogError::NotEnabled));
+
+    for page in 0..4 {
+        assert_eq!(vmm::unmap_guest_page(2, gpa(page * 0x1000)), Ok(()));
+    }
+    unsafe { memory::dealloc_contiguous_frames(ram, 4) };
+
+    drop(guard);
+}
+
+#[test]
+pub fn test_shared_phys_frame() {
+    use crate::memory::{AllocError, SharedPhysFrame, accounting, frame_table};
+    use memory_impl::{ARENA_PA, ARENA_SIZE};
+
+    let guard = memory_impl::enter_arena_test();
+    let total_frames = ARENA_SIZE / 0x1000;
+    let base = pa!(ARENA_PA);
+
+    vmm_impl::set_current_vm(10);
+    let mut parent = SharedPhysFrame::alloc_zeroed().unwrap();
+    assert_eq!(parent.start_paddr(), base);
+    assert_eq!(parent.owner(), 10);
+    parent.get_mut().unwrap()[0] = 1;
+    let mut child = parent.clone();
+    assert_eq!(frame_table::ref_count(base), 2);
+    assert!(child.get_mut().is_none());
+
+    // writing to a shared frame copies it
+    child.make_unique().unwrap()[1] = 2;
+    assert_eq!(child.start_paddr(), base + 0x1000);
+    assert_eq!(child.as_slice()[..3], [1, 2, 0]);
+    assert_eq!(parent.as_slice()[..3], [1, 0, 0]);
+    assert_eq!((parent.ref_count(), child.ref_count()), (1, 1));
+    assert_eq!(accounting::usage(10).frames, 2);
+
+    // the copy is charged to the owner
+    let shared = parent.clone();
+    accounting::set_limit(10, Some(2));
+    assert_eq!(parent.make_unique().unwrap_err(), AllocError::QuotaExceeded);
+    assert_eq!(parent.ref_count(), 2);
+
+    drop(parent);
+    drop(child);
+    assert_eq!(memory_impl::arena_free_frames(), total_frames - 1);
+    drop(shared);
+    assert_eq!(memory_impl::arena_free_frames(), total_frames);
+    assert_eq!(frame_table::ref_count(base), 0);
+    assert!(frame_table::snapshot().is_empty());
+    assert_eq!(accounting::remove(10).unwrap().frames, 0);
+    vmm_impl::set_current_vm(0);
+
+    drop(guard);
+}
+
+#[test]
+pub fn test_balloon() {
+    use crate::{
+        memory::{AllocError, accounting},
+        vmm::{
+            self, GuestAccess, GuestMemoryError,
+            balloon::{self, BalloonError},
+        },
+    };
+    use axaddrspace::GuestPhysAddr;
+    use memory_impl::ARENA_SIZE;
+
+    let guard = memory_impl::enter_arena_test();
+    let total_frames = ARENA_SIZE / 0x1000;
+    let gpa = GuestPhysAddr::from_usize;
+
+    assert_eq!(balloon::inflate(4, 2), 2);
+    for page in 1..=3 {
+        let frame = accounting::alloc_frame_for(4).unwrap();
+        vmm::map_guest_page(4, gpa(page * 0x1000), frame).unwrap();
+    }
+
+    // released pages are unmapped, and their frames deallocated and
+    // uncharged by the host
+    balloon::release_pages(4, &[gpa(0x1000), gpa(0x2000)]).unwrap();
+    assert_eq!(balloon::size(4), balloon::target(4));
+    assert!(balloon::contains(4, gpa(0x1234)));
+    assert_eq!(
+        vmm::translate_guest_phys(4, gpa(0x1000), GuestAccess::Read),
+        Err(GuestMemoryError::NotMapped(gpa(0x1000)))
+    );
+    assert_eq!(accounting::usage(4).frames, 1);
+    assert_eq!(memory_impl::arena_free_frames(), total_frames - 1);
+    assert_eq!(
+        balloon::release_pages(4, &[gpa(0x1000)]),
+        Err(BalloonError::InvalidParam)
+    );
+    assert_eq!(
+        balloon::release_pages(vmm_impl::VM_NUM, &[gpa(0x1000)]),
+        Err(BalloonError::Guest(GuestMemoryError::InvalidVm))
+    );
+    assert!(!balloon::contains(vmm_impl::VM_NUM, gpa(0x1000)));
+
+    // reclaimed pages are backed by zeroed frames charged again
+    balloon::reclaim_pages(4, &[gpa(0x2000)]).unwrap();
+    assert_eq!(
+        vmm::read_guest_obj::<[u64; 512]>(4, gpa(0x2000)),
+        Ok([0; 512])
+    );
+    assert_eq!(accounting::usage(4).frames, 2);
+    assert_eq!(
+        balloon::reclaim_pages(4, &[gpa(0x2000)]),
+        Err(BalloonError::InvalidParam)
+    );
+    accounting::set_limit(4, Some(2));
+    assert_eq!(
+        balloon::reclaim_pages(4, &[gpa(0x1000)]),
+        Err(BalloonError::Alloc(AllocError::QuotaExceeded))
+    );
+    assert!(balloon::contains(4, gpa(0x1000)));
+    assert_eq!(balloon::size(4), 1);
+
+    assert_eq!(balloon::remove(4), 1);
+    for page in 2..=3 {
+        vmm::unmap_guest_page(4, gpa(page * 0x1000)).unwrap();
+    }
+    assert_eq!(memory_impl::arena_free_frames(), total_frames);
+    assert_eq!(accounting::remove(4).unwrap().frames, 0);
+
+    drop(guard);
+}
+
+#[test]
+pub fn test_balloon_huge_page() {
+    use crate::memory::{PageSize, SizedPhysFrame, accounting};
+    use crate::vmm::{self, GuestAccess, GuestMemoryError, balloon};
+    use axaddrspace::GuestPhysAddr;
+    use memory_impl::ARENA_SIZE;
+
+    let guard = memory_impl::enter_arena_test();
+    let total_frames = ARENA_SIZE / 0x1000;
+    let gpa = GuestPhysAddr::from_usize;
+
+    // a 2 MiB host frame backs 512 guest pages
+    let huge = SizedPhysFrame::alloc_for(6, PageSize::Size2M).unwrap();
+    let huge_pa = huge.start_paddr();
+    vmm_impl::map_guest_memory(6, gpa(0x20_0000), huge_pa, 0x20_0000, true);
+    assert_eq!(accounting::usage(6).frames, 512);
+
+    // releasing a page of it unmaps the page, but keeps the frame alive
+    assert_eq!(balloon::inflate(6, 1), 1);
+    balloon::release_pages(6, &[gpa(0x20_1000)]).unwrap();
+    assert!(balloon::contains(6, gpa(0x20_1000)));
+    assert_eq!(
+        vmm::translate_guest_phys(6, gpa(0x20_1000), GuestAccess::Read),
+        Err(GuestMemoryError::NotMapped(gpa(0x20_1000)))
+    );
+    assert_eq!(
+        vmm::translate_guest_phys(6, gpa(0x20_2000), GuestAccess::Read),
+        Ok(huge_pa + 0x2000)
+    );
+    assert_eq!(accounting::usage(6).frames, 512);
+    assert_eq!(memory_impl::arena_free_frames(), total_frames - 512);
+
+    // the reclaimed page is backed by a new frame
+    balloon::reclaim_pages(6, &[gpa(0x20_1000)]).unwrap();
+    assert_eq!(accounting::usage(6).frames, 513);
+    assert_eq!(memory_impl::arena_free_frames(), total_frames - 513);
+
+    assert_eq!(balloon::remove(6), 0);
+    for page in 0..512 {
+        vmm::unmap_guest_page(6, gpa(0x20_0000 + page * 0x1000)).unwrap();
+    }
+    assert_eq!(accounting::usage(6).frames, 512);
+    drop(huge);
+    assert_eq!(memory_impl::arena_free_frames(), total_frames);
+    assert_eq!(accounting::remove(6).unwrap().frames, 0);
+
+    drop(guard);
+}
+
+mod iommu_impl {
+    use crate::iommu::{soft::SoftIommu, *};
+    use crate::memory::PhysAddr;
+    use axaddrspace::GuestPhysAddr;
+
+    pub static IOMMU: SoftIommu = SoftIommu::new();
+
+    pub struct IommuIfImpl;
+
+    #[crate::api_impl]
+    impl IommuIf for IommuIfImpl {
+        fn create_domain() -> Result<DomainId, IommuError> {
+            IOMMU.create_domain()
+        }
+
+        fn destroy_domain(domain: DomainId) -> Result<(), IommuError> {
+            IOMMU.destroy_domain(domain)
+        }
+
+        fn map(
+            domain: DomainId,
+            gpa: GuestPhysAddr,
+            hpa: PhysAddr,
+            size: usize,
+            perm: DmaPerm,
+        ) -> Result<(), IommuError> {
+            IOMMU.map(domain, gpa, hpa, size, perm)
+        }
+
+        fn unmap(domain: DomainId, gpa: GuestPhysAddr, size: usize) -> Result<(), IommuError> {
+            IOMMU.unmap(domain, gpa, size)
+        }
+
+        fn attach_device(domain: DomainId, stream_id: StreamId) -> Result<(), IommuError> {
+            IOMMU.attach_device(domain, stream_id)
+        }
+
+        fn detach_device(domain: DomainId, stream_id: StreamId) -> Result<(), IommuError> {
+            IOMMU.detach_device(domain, stream_id)
+        }
+
+        fn next_fault() -> Option<IommuFault> {
+            IOMMU.next_fault()
+        }
+    }
+}
+
+#[test]
+pub fn test_iommu() {
+    use crate::iommu::{self, DmaPerm, IommuError, IommuFault, IommuFaultKind};
+    use axaddrspace::GuestPhysAddr;
+    use iommu_impl::IOMMU;
+
+    let gpa = GuestPhysAddr::from_usize;
+    let domain = iommu::create_domain().unwrap();
+    iommu::map(
+        domain,
+        gpa(0x8000_0000),
+        pa!(0x4_0000_0000),
+        0x2000,
+        DmaPerm::READ,
+    )
+    .unwrap();
+    assert_eq!(
+        iommu::map(domain, gpa(0x8000_1000), pa!(0x1000), 0x1000, DmaPerm::READ),
+        Err(IommuError::AlreadyMapped)
+    );
+    assert_eq!(
+        iommu::map(domain, gpa(0x8000_2800), pa!(0x1000), 0x1000, DmaPerm::READ),
+        Err(IommuError::InvalidParam)
+    );
+    // ranges overflowing the guest or the host address space
+    assert_eq!(
+        iommu::map(
+            domain,
+            gpa(0usize.wrapping_sub(0x1000)),
+            pa!(0x1000),
+            0x2000,
+            DmaPerm::READ
+        ),
+        Err(IommuError::InvalidParam)
+    );
+    assert_eq!(
+        iommu::map(
+            domain,
+            gpa(0x9000_0000),
+            pa!(0usize.wrapping_sub(0x1000)),
+            0x2000,
+            DmaPerm::READ
+        ),
+        Err(IommuError::InvalidParam)
+    );
+    assert_eq!(
+        iommu::map(
+            domain,
+            gpa(0x9000_0000),
+            pa!(0x1000),
+            0x1000,
+            DmaPerm::empty()
+        ),
+        Err(IommuError::InvalidParam)
+    );
+    iommu::attach_device(domain, 0x10).unwrap();
+    assert_eq!(iommu::attach_device(domain, 0x10), Err(IommuError::Busy));
+
+    assert_eq!(
+        IOMMU.translate(0x10, gpa(0x8000_1234), DmaPerm::READ),
+        Ok(pa!(0x4_0000_1234))
+    );
+    let fault = IommuFault {
+        stream_id: 0x10,
+        domain: Some(domain),
+        iova: gpa(0x8000_0010),
+        access: DmaPerm::WRITE,
+        kind: IommuFaultKind::Permission,
+    };
+    assert_eq!(
+        IOMMU.translate(0x10, gpa(0x8000_0010), DmaPerm::WRITE),
+        Err(fault)
+    );
+    assert!(
+        IOMMU
+            .translate(0x10, gpa(0x8000_2000), DmaPerm::READ)
+            .is_err()
+    );
+    assert!(
+        IOMMU
+            .translate(0x11, gpa(0x8000_0000), DmaPerm::READ)
+            .is_err()
+    );
+    assert_eq!(iommu::next_fault(), Some(fault));
+    assert_eq!(
+        iommu::next_fault().unwrap().kind,
+        IommuFaultKind::Translation
+    );
+    assert_eq!(
+        iommu::next_fault().unwrap().kind,
+        IommuFaultKind::NotAttached
+    );
+    assert_eq!(iommu::next_fault(), None);
+
+    assert_eq!(
+        iommu::unmap(domain, gpa(0x8000_1000), 0x2000),
+        Err(IommuError::NotMapped)
+    );
+    iommu::unmap(domain, gpa(0x8000_0000), 0x2000).unwrap();
+    assert!(
+        IOMMU
+            .translate(0x10, gpa(0x8000_0000), DmaPerm::READ)
+            .is_err()
+    );
+
+    assert_eq!(iommu::destroy_domain(domain), Err(IommuError::Busy));
+    assert_eq!(
+        iommu::detach_device(domain + 1, 0x10),
+        Err(IommuError::NotAttached)
+    );
+    iommu::detach_device(domain, 0x10).unwrap();
+    iommu::destroy_domain(domain).unwrap();
+    assert_eq!(
+        iommu::destroy_domain(domain),
+        Err(IommuError::InvalidDomain)
+    );
+}
+
+mod default_api {
+    #[crate::api_def]
+    pub trait DefaultIf {
+        fn required() -> usize;
+
+        fn overridden() -> usize {
+            0
+        }
+
+        fn fallback(arg: usize) -> usize {
+            required() + arg
+        }
+    }
+
+    pub struct DefaultIfImpl;
+
+    #[crate::api_impl]
+    impl DefaultIf for DefaultIfImpl {
+        fn required() -> usize {
+            40
+        }
+
+        fn overridden() -> usize {
+            1
+        }
+    }
+}
+
+#[test]
+pub fn test_default_impl() {
+    assert_eq!(default_api::required(), 40);
+    assert_eq!(default_api::overridden(), 1);
+    assert_eq!(default_api::fallback(2), 42);
+}
+
+#[test]
+pub fn test_assert_implemented() {
+    crate::__priv::assert_api_implemented!(crate::memory::MemoryIf);
+    crate::__priv::assert_api_implemented!(default_api::DefaultIf);
+}
+
+#[test]
+pub fn test_memory_pressure() {
+    use crate::memory::{self, PressureLevel};
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use std::{boxed::Box, sync::Arc};
+
+    // handlers must not allocate, so record the call order as decimal digits
+    static ORDER: AtomicUsize = AtomicUsize::new(0);
+    let record = |digit| {
+        ORDER
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |order| {
+                Some(order * 10 + digit)
+            })
+            .unwrap();
+    };
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let calls2 = calls.clone();
+    let critical = memory::register_pressure_handler(
+        PressureLevel::Critical,
+        Box::new(move |_| {
+            record(3);
+            100
+        }),
+    );
+    let low = memory::register_pressure_handler(
+        PressureLevel::Low,
+        Box::new(move |level| {
+            calls2.fetch_add(1, Ordering::Relaxed);
+            record(1);
+            if level == PressureLevel::Low { 1 } else { 10 }
+        }),
+    );
+
+    assert_eq!(memory::notify_memory_pressure(PressureLevel::Low), 1);
+    assert_eq!(memory::notify_memory_pressure(PressureLevel::Medium), 10);
+    assert_eq!(memory::notify_memory_pressure(PressureLevel::Critical), 110);
+    assert_eq!(calls.load(Ordering::Relaxed), 3);
+    assert_eq!(ORDER.load(Ordering::Relaxed), 1113);
+
+    // the handlers are moved to larger storage as more are registered
+    let tokens = (0..10)
+        .map(|_| memory::register_pressure_handler(PressureLevel::Medium, Box::new(|_| 1000)))
+        .collect::<std::vec::Vec<_>>();
+    assert_eq!(
+        memory::notify_memory_pressure(PressureLevel::Medium),
+        10_010
+    );
+    for token in tokens {
+        assert!(memory::unregister_pressure_handler(token));
+    }
+
+    assert!(memory::unregister_pressure_handler(low));
+    assert!(!memory::unregister_pressure_handler(low));
+    assert_eq!(memory::notify_memory_pressure(PressureLevel::Medium), 0);
+    assert!(memory::unregister_pressure_handler(critical));
+    assert_eq!(memory::notify_memory_pressure(PressureLevel::Critical), 0);
+}
+
+#[cfg(feature = "mock")]
+mod mock_api {
+    #[crate::api_def(mock)]
+    pub trait MockIf {
+        fn required(arg: usize) -> usize;
+
+        fn with_default(arg: usize) -> usize {
+            required(arg) + 1
+        }
+    }
+
+    pub struct MockIfImpl;
+
+    // Mocks are never exported, link them like `axvisor_api_mock` does.
+    #[crate::api_impl]
+    impl MockIf for MockIfImpl {
+        fn required(arg: usize) -> usize {
+            <mock::Mock as MockIf>::required(arg)
+        }
+
+        fn with_default(arg: usize) -> usize {
+            <mock::Mock as MockIf>::with_default(arg)
+        }
+    }
+}
+
+#[cfg(feature = "mock")]
+#[test]
+pub fn test_mock_default_body() {
+    let _fixture = crate::mock::fixture();
+    mock_api::mock::reset();
+
+    mock_api::mock::required.set(|(arg,)| arg * 2);
+    assert_eq!(mock_api::with_default(20), 41);
+
+    mock_api::mock::with_default.set(|(arg,)| arg);
+    assert_eq!(mock_api::with_default(20), 20);
+}
+
+#[cfg(feature = "trace")]
+mod trace_api {
+    extern crate std;
+
+    use std::boxed::Box;
+
+    #[crate::api_def(trace)]
+    pub trait TraceIf {
+        fn add(a: usize, b: usize) -> usize;
+
+        fn call(f: Box<dyn Fn() -> usize>) -> Option<usize>;
+    }
+
+    pub struct TraceIfImpl;
+
+    #[crate::api_impl]
+    impl TraceIf for TraceIfImpl {
+        fn add(a: usize, b: usize) -> usize {
+            a + b
+        }
+
+        fn call(f: Box<dyn Fn() -> usize>) -> Option<usize> {
+            Some(f())
+        }
+    }
+}
+
+#[cfg(feature = "trace")]
+#[test]
+pub fn test_trace() {
+    use crate::{
+        ApiFn,
+        trace::{self, TraceSink},
+    };
+    use core::fmt::Debug;
+    use std::{boxed::Box, format, string::String, sync::Mutex, vec::Vec};
+
+    static EVENTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    struct RecordingSink;
+
+    impl TraceSink for RecordingSink {
+        fn enter(&self, api: ApiFn, args: &[(&'static str, &dyn Debug)]) {
+            // other tests may call traced APIs concurrently
+            if api.trait_name == "TraceIf" {
+                EVENTS.lock().unwrap().push(format!("-> {api} {args:?}"));
+            }
+        }
+
+        fn exit(&self, api: ApiFn, ret: &dyn Debug) {
+            if api.trait_name == "TraceIf" {
+                EVENTS.lock().unwrap().push(format!("<- {api} = {ret:?}"));
+            }
+        }
+    }
+
+    trace::set_sink(&RecordingSink);
+    assert_eq!(trace_api::add(1, 2), 3);
+    assert_eq!(trace_api::call(Box::new(|| 42)), Some(42));
+    // the untraced callers bypass the sink
+    assert_eq!(trace_api::untraced::add(5, 6), 11);
+    trace::clear_sink();
+    assert_eq!(trace_api::add(3, 4), 7);
+
+    assert_eq!(
+        *EVENTS.lock().unwrap(),
+        [
+            r#"-> TraceIf::add [("a", 1), ("b", 2)]"#,
+            "<- TraceIf::add = 3",
+            r#"-> TraceIf::call [("f", ..)]"#,
+            "<- TraceIf::call = Some(42)",
+        ]
+    );
+}
+
+#[cfg(feature = "stats")]
+mod time_impl {
+    extern crate std;
+
+    use crate::time::{CancelToken, Nanos, Ticks, TimeValue};
+    use std::{
+        boxed::Box,
+        sync::atomic::{AtomicU64, Ordering},
+    };
+
+    static TICKS: AtomicU64 = AtomicU64::new(0);
+
+    pub struct TimeIfImpl;
+
+    // Needed by the statistics counters, every query advances the clock by a
+    // tick.
+    #[crate::api_impl]
+    impl crate::time::TimeIf for TimeIfImpl {
+        fn current_ticks() -> Ticks {
+            TICKS.fetch_add(1, Ordering::Relaxed)
+        }
+
+        fn ticks_to_nanos(ticks: Ticks) -> Nanos {
+            ticks
+        }
+
+        fn nanos_to_ticks(nanos: Nanos) -> Ticks {
+            nanos
+        }
+
+        fn register_timer(
+            _deadline: TimeValue,
+            _callback: Box<dyn FnOnce(TimeValue) + Send + 'static>,
+        ) -> CancelToken {
+            unimplemented!()
+        }
+
+        fn cancel_timer(_token: CancelToken) {
+            unimplemented!()
+        }
+    }
+}
+
+#[cfg(feature = "stats")]
+mod stats_api {
+    #[crate::api_def(stats)]
+    pub trait StatsIf {
+        fn counted(arg: usize) -> usize;
+    }
+
+    pub struct StatsIfImpl;
+
+    #[crate::api_impl]
+    impl StatsIf for StatsIfImpl {
+        fn counted(arg: usize) -> usize {
+            arg
+        }
+    }
+}
+
+#[cfg(feature = "stats")]
+#[test]
+pub fn test_stats() {
+    use crate::stats;
+
+    assert_eq!(stats::get("StatsIf", "counted"), None);
+
+    for i in 0..3 {
+        assert_eq!(stats_api::counted(i), i);
+    }
+
+    let counted = stats::get("StatsIf", "counted").unwrap();
+    assert_eq!(counted.calls, 3);
+    assert!(counted.max_ticks <= counted.total_ticks);
+    assert!(stats::snapshot().any(|stats| stats.api == counted.api));
+}
+
+mod versioned_api {
+    #[crate::api_def(version = 2)]
+    pub trait VersionedIf {
+        fn required() -> usize;
+
+        fn with_default() -> usize {
+            required() + 1
+        }
+    }
+
+    pub struct VersionedIfImpl;
+
+    #[crate::api_impl(version = 2)]
+    impl VersionedIf for VersionedIfImpl {
+        fn required() -> usize {
+            41
+        }
+    }
+}
+
+#[test]
+pub fn test_versioned() {
+    crate::__priv::assert_api_implemented!(versioned_api::VersionedIf);
+    assert_eq!(versioned_api::required(), 41);
+    assert_eq!(versioned_api::with_default(), 42);
+}
+
+mod dynamic_api {
+    #[crate::api_def(dynamic)]
+    pub trait ClockIf {
+        fn now() -> u64;
+
+        fn name() -> &'static str {
+            "default"
+        }
+    }
+
+    pub struct RealClock;
+
+    #[crate::api_impl]
+    impl ClockIf for RealClock {
+        fn now() -> u64 {
+            1
+        }
+    }
+
+    pub struct VirtualClock;
+
+    #[crate::api_impl(dynamic)]
+    impl ClockIf for VirtualClock {
+        fn now() -> u64 {
+            100
+        }
+
+        fn name() -> &'static str {
+            "virtual"
+        }
+    }
+}
+
+#[test]
+pub fn test_dynamic() {
+    use dynamic_api::{DynClockIf, VirtualClock};
+
+    assert_eq!(dynamic_api::now(), 1);
+    assert_eq!(dynamic_api::name(), "default");
+    assert!(crate::installed::<DynClockIf>().is_none());
+
+    assert!(crate::install::<DynClockIf>(DynClockIf::vtable::<VirtualClock>()).is_none());
+    assert_eq!(dynamic_api::now(), 100);
+    assert_eq!(dynamic_api::name(), "virtual");
+
+    assert!(crate::uninstall::<DynClockIf>().is_some());
+    assert_eq!(dynamic_api::now(), 1);
+    assert_eq!(dynamic_api::name(), "default");
+}
+
+#[cfg(feature = "intercept")]
+mod intercept_api {
+    #[crate::api_def(intercept)]
+    pub trait InterceptIf {
+        fn alloc() -> Option<usize>;
+
+        fn deadline(deadline: u64) -> u64;
+    }
+
+    pub struct InterceptIfImpl;
+
+    #[crate::api_impl]
+    impl InterceptIf for InterceptIfImpl {
+        fn alloc() -> Option<usize> {
+            Some(0x1000)
+        }
+
+        fn deadline(deadline: u64) -> u64 {
+            deadline
+        }
+    }
+}
+
+#[cfg(feature = "intercept")]
+#[test]
+pub fn test_intercept_hooks() {
+    use intercept_api::intercept;
+
+    intercept::deadline.set_pre(|(deadline,)| {
+        *deadline += 10;
+        None
+    });
+    assert_eq!(intercept_api::deadline(5), 15);
+
+    intercept::deadline.set_pre(|&mut (deadline,)| (deadline == 0).then_some(42));
+    assert_eq!(intercept_api::deadline(0), 42);
+    assert_eq!(intercept_api::deadline(1), 1);
+
+    intercept::deadline.set_post(|ret| ret * 2);
+    assert_eq!(intercept_api::deadline(1), 2);
+    // overridden return values skip the post-hook
+    assert_eq!(intercept_api::deadline(0), 42);
+
+    // hooks stay installed while they run
+    intercept::deadline.set_pre(|&mut (deadline,)| {
+        (deadline > 0).then(|| intercept_api::deadline(deadline - 1) + 100)
+    });
+    assert_eq!(intercept_api::deadline(2), 200);
+
+    intercept::reset();
+    assert_eq!(intercept_api::deadline(5), 5);
+}
+
+#[cfg(feature = "intercept")]
+#[test]
+pub fn test_intercept_faults() {
+    use crate::intercept::FaultPolicy;
+    use intercept_api::intercept;
+    use std::vec::Vec;
+
+    let results = || {
+        (0..6)
+            .map(|_| intercept_api::alloc().is_none())
+            .collect::<Vec<_>>()
+    };
+
+    intercept::alloc.inject_fault(FaultPolicy::every_nth(3), || None);
+    assert_eq!(results(), [false, false, true, false, false, true]);
+
+    intercept::alloc.inject_fault(FaultPolicy::after_budget(4), || None);
+    assert_eq!(results(), [false, false, false, false, true, true]);
+
+    intercept::alloc.inject_fault(FaultPolicy::random(7, 2), || None);
+    let first = results();
+    assert!(first.contains(&true) && first.contains(&false));
+    intercept::alloc.inject_fault(FaultPolicy::random(7, 2), || None);
+    assert_eq!(results(), first);
+
+    intercept::reset();
+    assert_eq!(results(), [false; 6]);
+}
+
+mod foo_api {
+    #[crate::api_def(namespace = "Foo")]
+    pub trait PersonalityIf {
+        fn name() -> &'static str;
+    }
+
+    pub struct PersonalityIfImpl;
+
+    #[crate::api_impl(namespace = "Foo")]
+    impl PersonalityIf for PersonalityIfImpl {
+        fn name() -> &'static str {
+            "foo"
+        }
+    }
+}
+
+mod bar_api {
+    #[crate::api_def(namespace = "Bar")]
+    pub trait PersonalityIf {
+        fn name() -> &'static str;
+    }
+
+    pub struct PersonalityIfImpl;
+
+    #[crate::api_impl(namespace = "Bar")]
+    impl PersonalityIf for PersonalityIfImpl {
+        fn name() -> &'static str {
+            "bar"
+        }
+    }
+}
+
+#[test]
+pub fn test_namespace() {
+    crate::__priv::assert_api_implemented!(foo_api::PersonalityIf);
+    crate::__priv::assert_api_implemented!(bar_api::PersonalityIf);
+    assert_eq!(foo_api::name(), "foo");
+    assert_eq!(bar_api::name(), "bar");
+}
+
+mod unsafe_api {
+    #[crate::api_def]
+    pub trait UnsafeIf {
+        unsafe fn read(ptr: *const usize) -> usize;
+
+        unsafe fn read_twice(ptr: *const usize) -> usize {
+            unsafe { read(ptr) * 2 }
+        }
+    }
+
+    pub struct UnsafeIfImpl;
+
+    #[crate::api_impl]
+    impl UnsafeIf for UnsafeIfImpl {
+        unsafe fn read(ptr: *const usize) -> usize {
+            unsafe { *ptr }
+        }
+    }
+}
+
+#[test]
+fn test_unsafe() {
+    let value = 21;
+    assert_eq!(unsafe { unsafe_api::read(&value) }, 21);
+    assert_eq!(unsafe { unsafe_api::read_twice(&value) }, 42);
+}
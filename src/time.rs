@@ -56,6 +56,10 @@
 //!     // ... implement other functions
 //! }
 //! ```
+//!
+//! The implementation can also be replaced at runtime, for example by a
+//! virtual clock in simulations, by installing another implementation for
+//! [`DynTimeIf`], see [`dynamic`](crate::dynamic).
 
 extern crate alloc;
 
@@ -88,7 +92,7 @@ pub type CancelToken = usize;
 /// This trait defines the core time management interface required by the
 /// hypervisor. Implementations should be provided by the host system or HAL
 /// layer.
-#[crate::api_def]
+#[crate::api_def(mock, trace, stats, intercept, dynamic)]
 pub trait TimeIf {
     /// Get the current tick count from the hardware timer.
     ///
@@ -0,0 +1,156 @@
+// Copyright 2025 The Axvisor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tracing of the AxVisor API calls.
+//!
+//! This module is only available with the `trace` feature. With it, the
+//! caller of every API function defined with `#[api_def(trace)]` reports each
+//! call to the [`TraceSink`] installed by [`set_sink`], before and after
+//! calling the implementation. All API traits of this crate are traced.
+//!
+//! # Usage
+//!
+//! ```rust,ignore
+//! use core::fmt::Debug;
+//! use axvisor_api::{ApiFn, trace::{self, TraceSink}};
+//!
+//! struct LogSink;
+//!
+//! impl TraceSink for LogSink {
+//!     fn enter(&self, api: ApiFn, args: &[(&'static str, &dyn Debug)]) {
+//!         log::trace!("-> {api} {args:?}");
+//!     }
+//!
+//!     fn exit(&self, api: ApiFn, ret: &dyn Debug) {
+//!         log::trace!("<- {api} = {ret:?}");
+//!     }
+//! }
+//!
+//! trace::set_sink(&LogSink);
+//! ```
+//!
+//! Arguments and return values are reported through their [`Debug`]
+//! implementation. Those of types not implementing [`Debug`], like the
+//! callbacks of `time::register_timer`, are reported as `..`.
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use core::{
+    fmt::{self, Debug},
+    ptr,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+use crate::ApiFn;
+
+/// A receiver of the traced API calls.
+///
+/// The sink is called on the calling CPU, possibly in interrupt context, so
+/// it should be quick and must not call traced API functions itself.
+pub trait TraceSink: Sync {
+    /// Called before the implementation of `api` is called, with the names
+    /// and values of the arguments.
+    fn enter(&self, api: ApiFn, args: &[(&'static str, &dyn Debug)]);
+
+    /// Called after the implementation of `api` returns, with the return
+    /// value.
+    fn exit(&self, api: ApiFn, ret: &dyn Debug);
+}
+
+/// The installed sink, or null.
+///
+/// The reference to the sink is a fat pointer, so it is boxed to be swapped
+/// atomically. The boxes are never freed, as the sink may be read
+/// concurrently with its replacement.
+static SINK: AtomicPtr<&'static dyn TraceSink> = AtomicPtr::new(ptr::null_mut());
+
+/// Install the sink receiving the traced API calls, replacing the previous
+/// one if any.
+///
+/// Every call leaks a pointer-sized allocation, so sinks should be installed
+/// once, or a few times in tests.
+pub fn set_sink(sink: &'static dyn TraceSink) {
+    SINK.store(Box::into_raw(Box::new(sink)), Ordering::Release);
+}
+
+/// Remove the installed sink, if any, so API calls are no longer traced.
+pub fn clear_sink() {
+    SINK.store(ptr::null_mut(), Ordering::Release);
+}
+
+/// Get the installed sink.
+///
+/// The sink is read without locking, so tracing is safe in interrupt context
+/// and a sink is free to replace itself.
+fn sink() -> Option<&'static dyn TraceSink> {
+    // SAFETY: non-null pointers are leaked boxes, which are never freed nor
+    // modified.
+    unsafe { SINK.load(Ordering::Acquire).as_ref().copied() }
+}
+
+/// Report the entry of an API call to the installed sink.
+#[doc(hidden)]
+pub fn enter(api: ApiFn, args: &[(&'static str, &dyn Debug)]) {
+    if let Some(sink) = sink() {
+        sink.enter(api, args);
+    }
+}
+
+/// Report the exit of an API call to the installed sink.
+#[doc(hidden)]
+pub fn exit(api: ApiFn, ret: &dyn Debug) {
+    if let Some(sink) = sink() {
+        sink.exit(api, ret);
+    }
+}
+
+/// Wrapper of a traced value to report it through [`Debug`] if possible.
+///
+/// Generated callers call `(&Traced(&value)).as_debug()`, which resolves to
+/// [`TraceDebug::as_debug`] if the value implements [`Debug`], and
+/// [`TraceOpaque::as_debug`] otherwise.
+#[doc(hidden)]
+pub struct Traced<'a, T>(pub &'a T);
+
+#[doc(hidden)]
+pub trait TraceDebug {
+    fn as_debug(&self) -> &dyn Debug;
+}
+
+impl<T: Debug> TraceDebug for Traced<'_, T> {
+    fn as_debug(&self) -> &dyn Debug {
+        self.0
+    }
+}
+
+#[doc(hidden)]
+pub trait TraceOpaque {
+    fn as_debug(&self) -> &dyn Debug;
+}
+
+impl<T> TraceOpaque for &Traced<'_, T> {
+    fn as_debug(&self) -> &dyn Debug {
+        &Opaque
+    }
+}
+
+/// A placeholder for values not implementing [`Debug`].
+struct Opaque;
+
+impl Debug for Opaque {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("..")
+    }
+}
@@ -25,18 +25,31 @@
 //! - Getting information about VMs and their vCPUs
 //! - Injecting interrupts into virtual CPUs
 //! - Timer expiration notifications
+//! - Access to guest physical memory
+//! - Tracking of the guest pages written since the last checkpoint, for live
+//!   migration and snapshots
+//! - Memory ballooning, see the [`balloon`] module
 //!
 //! # Types
 //!
 //! - [`VMId`] - Virtual machine identifier.
 //! - [`VCpuId`] - Virtual CPU identifier.
 //! - [`InterruptVector`] - Interrupt vector number.
+//! - [`GuestAccess`] - The kind of an access to guest memory.
+//! - [`GuestMemoryError`] - The reason why an access to guest memory failed.
+//! - [`Pod`] - Types which can be read from and written to guest memory.
+//! - [`DirtyBitmap`] - The dirty state of a range of guest pages.
+//! - [`DirtyLogError`] - The reason why a dirty log operation failed.
 //!
 //! # Helper Functions
 //!
 //! In addition to the core API trait, this module provides helper functions:
 //! - [`current_vm_vcpu_num`] - Get the vCPU count of the current VM.
 //! - [`current_vm_active_vcpus`] - Get the active vCPU mask of the current VM.
+//! - [`read_guest_memory`] / [`write_guest_memory`] - Copy data from or to
+//!   the guest physical memory of a VM.
+//! - [`read_guest_obj`] / [`write_guest_obj`] - Read or write a typed value
+//!   in the guest physical memory of a VM.
 //!
 //! # Implementation
 //!
@@ -54,6 +67,22 @@
 //!     // ... implement other functions
 //! }
 //! ```
+//!
+//! Hosts without hardware dirty tracking may forward the dirty log functions
+//! to the software model in the [`dirty_log`] module.
+
+extern crate alloc;
+
+use alloc::{vec, vec::Vec};
+use core::{fmt, mem::MaybeUninit};
+
+use axaddrspace::GuestPhysAddr;
+use memory_addr::{MemoryAddr, PAGE_SIZE_4K};
+
+use crate::memory::{self, PhysAddr};
+
+pub mod balloon;
+pub mod dirty_log;
 
 /// Virtual machine identifier type.
 ///
@@ -71,12 +100,142 @@ pub type VCpuId = usize;
 /// Represents the interrupt vector number to be injected into a guest.
 pub type InterruptVector = u8;
 
+/// The kind of an access to guest memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GuestAccess {
+    /// A read access.
+    Read,
+    /// A write access.
+    Write,
+}
+
+/// The error returned when an access to guest memory fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum GuestMemoryError {
+    /// The VM does not exist.
+    InvalidVm,
+    /// The guest physical address is not mapped.
+    NotMapped(GuestPhysAddr),
+    /// The guest physical address is mapped without the permission of the
+    /// access.
+    PermissionDenied(GuestPhysAddr),
+    /// The translation of guest physical addresses is not supported.
+    Unsupported,
+}
+
+impl fmt::Display for GuestMemoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidVm => write!(f, "invalid VM"),
+            Self::NotMapped(gpa) => write!(f, "guest physical address {gpa:?} not mapped"),
+            Self::PermissionDenied(gpa) => {
+                write!(f, "permission denied at guest physical address {gpa:?}")
+            }
+            Self::Unsupported => write!(f, "guest memory translation not supported"),
+        }
+    }
+}
+
+impl core::error::Error for GuestMemoryError {}
+
+/// The error returned when a dirty log operation fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DirtyLogError {
+    /// The VM does not exist.
+    InvalidVm,
+    /// Dirty logging is not enabled for the VM.
+    NotEnabled,
+    /// The address is not page-aligned, or the range overflows.
+    InvalidParam,
+    /// Dirty logging is not supported.
+    Unsupported,
+}
+
+impl fmt::Display for DirtyLogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidVm => write!(f, "invalid VM"),
+            Self::NotEnabled => write!(f, "dirty logging not enabled"),
+            Self::InvalidParam => write!(f, "invalid parameter"),
+            Self::Unsupported => write!(f, "dirty logging not supported"),
+        }
+    }
+}
+
+impl core::error::Error for DirtyLogError {}
+
+/// The dirty state of a range of 4 KiB guest pages, one bit per page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirtyBitmap {
+    start: GuestPhysAddr,
+    num_pages: usize,
+    bits: Vec<u64>,
+}
+
+impl DirtyBitmap {
+    /// Create a bitmap of `num_pages` clean pages starting at `start`, which
+    /// must be page-aligned.
+    pub fn new(start: GuestPhysAddr, num_pages: usize) -> Self {
+        assert!(start.is_aligned_4k(), "unaligned start of dirty bitmap");
+        Self {
+            start,
+            num_pages,
+            bits: vec![0; num_pages.div_ceil(64)],
+        }
+    }
+
+    /// The guest physical address of the first page.
+    pub fn start(&self) -> GuestPhysAddr {
+        self.start
+    }
+
+    /// The number of pages covered by the bitmap.
+    pub fn num_pages(&self) -> usize {
+        self.num_pages
+    }
+
+    /// The bits of the bitmap, the state of page `i` being bit `i % 64` of
+    /// word `i / 64`. Bits past the last page are zero.
+    pub fn as_words(&self) -> &[u64] {
+        &self.bits
+    }
+
+    /// Whether the `index`-th page is dirty.
+    pub fn is_dirty(&self, index: usize) -> bool {
+        assert!(index < self.num_pages, "page index out of bounds");
+        self.bits[index / 64] & (1 << (index % 64)) != 0
+    }
+
+    /// Mark the `index`-th page as dirty.
+    pub fn set_dirty(&mut self, index: usize) {
+        assert!(index < self.num_pages, "page index out of bounds");
+        self.bits[index / 64] |= 1 << (index % 64);
+    }
+
+    /// The number of dirty pages.
+    pub fn count_dirty(&self) -> usize {
+        self.bits
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+
+    /// Iterate over the guest physical addresses of the dirty pages.
+    pub fn dirty_pages(&self) -> impl Iterator<Item = GuestPhysAddr> + '_ {
+        (0..self.num_pages)
+            .filter(|&index| self.is_dirty(index))
+            .map(|index| self.start + index * PAGE_SIZE_4K)
+    }
+}
+
 /// The API trait for virtual machine management functionalities.
 ///
 /// This trait defines the core VM management interface required by the
 /// hypervisor components. Implementations should be provided by the VMM
 /// layer.
-#[crate::api_def]
+#[crate::api_def(mock, trace, stats, intercept)]
 pub trait VmmIf {
     /// Get the identifier of the current virtual machine.
     ///
@@ -160,6 +319,169 @@ pub trait VmmIf {
     /// This API may be revised in future versions as the timer virtualization
     /// design evolves.
     fn notify_vcpu_timer_expired(vm_id: VMId, vcpu_id: VCpuId);
+
+    /// Translate a guest physical address of a virtual machine to a host
+    /// physical address, checking the permissions of the access.
+    ///
+    /// Use [`read_guest_memory`] and [`write_guest_memory`] instead, which
+    /// handle ranges crossing page boundaries.
+    ///
+    /// The default implementation reports the translation as unsupported.
+    ///
+    /// # Arguments
+    ///
+    /// * `vm_id` - The identifier of the virtual machine.
+    /// * `gpa` - The guest physical address to translate.
+    /// * `access` - The kind of the access.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(PhysAddr)` - The host physical address. The rest of the 4 KiB
+    ///   page containing `gpa` is mapped contiguously after it.
+    /// - `Err(GuestMemoryError)` - The reason why the translation failed.
+    fn translate_guest_phys(
+        vm_id: VMId,
+        gpa: GuestPhysAddr,
+        access: GuestAccess,
+    ) -> Result<PhysAddr, GuestMemoryError> {
+        let _ = (vm_id, gpa, access);
+        Err(GuestMemoryError::Unsupported)
+    }
+
+    /// Map a host frame at a guest physical page of a virtual machine, with
+    /// read, write and execute permissions.
+    ///
+    /// The mapping takes the ownership of the frame, which must have been
+    /// allocated for the VM with
+    /// [`accounting::alloc_frame_for`](crate::memory::accounting::alloc_frame_for)
+    /// or a variant of it, so [`unmap_guest_page`] deallocates it with
+    /// [`accounting::dealloc_frame_for`](crate::memory::accounting::dealloc_frame_for).
+    ///
+    /// The default implementation reports the mapping as unsupported.
+    ///
+    /// # Arguments
+    ///
+    /// * `vm_id` - The identifier of the virtual machine.
+    /// * `gpa` - The page-aligned guest physical address to map.
+    /// * `hpa` - The host physical address of the frame.
+    fn map_guest_page(
+        vm_id: VMId,
+        gpa: GuestPhysAddr,
+        hpa: PhysAddr,
+    ) -> Result<(), GuestMemoryError> {
+        let _ = (vm_id, gpa, hpa);
+        Err(GuestMemoryError::Unsupported)
+    }
+
+    /// Unmap a guest physical page of a virtual machine, and dispose of the
+    /// frame backing it.
+    ///
+    /// Only the host knows how the page is backed, so it disposes of the
+    /// frame: a frame mapped with [`map_guest_page`] is deallocated and
+    /// uncharged from the VM, while a page backed by a part of a larger
+    /// allocation, e.g. a huge page, may keep the allocation alive until the
+    /// rest of it is unmapped.
+    ///
+    /// The default implementation reports the unmapping as unsupported.
+    ///
+    /// # Arguments
+    ///
+    /// * `vm_id` - The identifier of the virtual machine.
+    /// * `gpa` - The page-aligned guest physical address to unmap.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(())` - The page was unmapped.
+    /// - `Err(GuestMemoryError)` - The reason why the page was not unmapped.
+    fn unmap_guest_page(vm_id: VMId, gpa: GuestPhysAddr) -> Result<(), GuestMemoryError> {
+        let _ = (vm_id, gpa);
+        Err(GuestMemoryError::Unsupported)
+    }
+
+    /// Notify the balloon device of a virtual machine that the number of
+    /// pages the guest should give back to the host changed.
+    ///
+    /// This is called by [`balloon::inflate`] and [`balloon::deflate`]. Hosts
+    /// forward it to the virtio-balloon device of the VM, which asks the
+    /// guest driver to adjust the balloon. The default implementation does
+    /// nothing.
+    ///
+    /// # Arguments
+    ///
+    /// * `vm_id` - The identifier of the virtual machine.
+    /// * `target_pages` - The requested size of the balloon, in 4 KiB pages.
+    fn balloon_target_changed(vm_id: VMId, target_pages: usize) {
+        let _ = (vm_id, target_pages);
+    }
+
+    /// Start logging the guest pages written by a virtual machine.
+    ///
+    /// All pages are clean right after logging is enabled. Enabling logging
+    /// again has no effect.
+    ///
+    /// The default implementation reports dirty logging as unsupported.
+    ///
+    /// # Arguments
+    ///
+    /// * `vm_id` - The identifier of the virtual machine.
+    fn enable_dirty_log(vm_id: VMId) -> Result<(), DirtyLogError> {
+        let _ = vm_id;
+        Err(DirtyLogError::Unsupported)
+    }
+
+    /// Stop logging the guest pages written by a virtual machine, discarding
+    /// the pages logged since the last fetch.
+    ///
+    /// The default implementation reports dirty logging as unsupported.
+    ///
+    /// # Arguments
+    ///
+    /// * `vm_id` - The identifier of the virtual machine.
+    fn disable_dirty_log(vm_id: VMId) -> Result<(), DirtyLogError> {
+        let _ = vm_id;
+        Err(DirtyLogError::Unsupported)
+    }
+
+    /// Fetch the pages of a range written by a virtual machine since logging
+    /// was enabled or the range was last fetched, and mark them as clean.
+    ///
+    /// The default implementation reports dirty logging as unsupported.
+    ///
+    /// # Arguments
+    ///
+    /// * `vm_id` - The identifier of the virtual machine.
+    /// * `gpa` - The page-aligned guest physical address of the range.
+    /// * `num_pages` - The number of 4 KiB pages in the range.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(DirtyBitmap)` - The dirty pages of the range.
+    /// - `Err(DirtyLogError)` - The reason why the log could not be fetched.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// use axvisor_api::vmm::{enable_dirty_log, fetch_and_clear_dirty_log};
+    ///
+    /// enable_dirty_log(vm_id)?;
+    /// loop {
+    ///     let dirty = fetch_and_clear_dirty_log(vm_id, ram_start, ram_pages)?;
+    ///     if dirty.count_dirty() < THRESHOLD {
+    ///         break;
+    ///     }
+    ///     for gpa in dirty.dirty_pages() {
+    ///         // Send the page to the destination
+    ///     }
+    /// }
+    /// ```
+    fn fetch_and_clear_dirty_log(
+        vm_id: VMId,
+        gpa: GuestPhysAddr,
+        num_pages: usize,
+    ) -> Result<DirtyBitmap, DirtyLogError> {
+        let _ = (vm_id, gpa, num_pages);
+        Err(DirtyLogError::Unsupported)
+    }
 }
 
 /// Get the number of virtual CPUs in the current virtual machine.
@@ -195,3 +517,159 @@ pub fn current_vm_vcpu_num() -> usize {
 pub fn current_vm_active_vcpus() -> usize {
     active_vcpus(current_vm_id()).unwrap()
 }
+
+/// Types which can be safely read from and written to guest memory as raw
+/// bytes.
+///
+/// # Safety
+///
+/// The type must have no padding, no invalid bit patterns and no pointers,
+/// like integers and `#[repr(C)]` structs of such types.
+pub unsafe trait Pod: Copy + 'static {}
+
+macro_rules! impl_pod {
+    ($($ty:ty),*) => {
+        $(unsafe impl Pod for $ty {})*
+    };
+}
+
+impl_pod!(
+    u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize
+);
+
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
+
+/// Access the guest physical memory of a VM page by page, calling `f` with
+/// the host virtual address of every chunk and its offset in the range.
+fn for_each_guest_chunk(
+    vm_id: VMId,
+    gpa: GuestPhysAddr,
+    len: usize,
+    access: GuestAccess,
+    mut f: impl FnMut(*mut u8, usize, usize),
+) -> Result<(), GuestMemoryError> {
+    let mut offset = 0;
+    while offset < len {
+        let chunk_gpa = gpa
+            .checked_add(offset)
+            .ok_or(GuestMemoryError::NotMapped(gpa))?;
+        let chunk_len = (len - offset).min(PAGE_SIZE_4K - chunk_gpa.align_offset_4k());
+        let hpa = translate_guest_phys(vm_id, chunk_gpa, access)?;
+        let hva = memory::try_phys_to_virt_range(hpa, chunk_len)
+            .ok_or(GuestMemoryError::NotMapped(chunk_gpa))?;
+        f(hva.as_mut_ptr(), offset, chunk_len);
+        offset += chunk_len;
+    }
+    Ok(())
+}
+
+/// Copy data from the guest physical memory of a VM.
+///
+/// The range may cross page boundaries, and be mapped to discontiguous host
+/// physical memory.
+///
+/// # Arguments
+///
+/// * `vm_id` - The identifier of the virtual machine.
+/// * `gpa` - The guest physical address to read from.
+/// * `buf` - The buffer to fill.
+///
+/// # Errors
+///
+/// Returns the error of [`translate_guest_phys`] for the first page of the
+/// range which cannot be read, in which case `buf` may be partially filled.
+pub fn read_guest_memory(
+    vm_id: VMId,
+    gpa: GuestPhysAddr,
+    buf: &mut [u8],
+) -> Result<(), GuestMemoryError> {
+    for_each_guest_chunk(
+        vm_id,
+        gpa,
+        buf.len(),
+        GuestAccess::Read,
+        |hva, offset, len| {
+            // SAFETY: the chunk is mapped in the host virtual address space, and
+            // in bounds of the buffer.
+            unsafe { core::ptr::copy_nonoverlapping(hva, buf[offset..].as_mut_ptr(), len) }
+        },
+    )
+}
+
+/// Copy data to the guest physical memory of a VM.
+///
+/// The range may cross page boundaries, and be mapped to discontiguous host
+/// physical memory.
+///
+/// # Arguments
+///
+/// * `vm_id` - The identifier of the virtual machine.
+/// * `gpa` - The guest physical address to write to.
+/// * `buf` - The data to write.
+///
+/// # Errors
+///
+/// Returns the error of [`translate_guest_phys`] for the first page of the
+/// range which cannot be written, in which case the data may be partially
+/// written.
+pub fn write_guest_memory(
+    vm_id: VMId,
+    gpa: GuestPhysAddr,
+    buf: &[u8],
+) -> Result<(), GuestMemoryError> {
+    for_each_guest_chunk(
+        vm_id,
+        gpa,
+        buf.len(),
+        GuestAccess::Write,
+        |hva, offset, len| {
+            // SAFETY: the chunk is mapped in the host virtual address space, and
+            // in bounds of the buffer.
+            unsafe { core::ptr::copy_nonoverlapping(buf[offset..].as_ptr(), hva, len) }
+        },
+    )
+}
+
+/// Read a value from the guest physical memory of a VM.
+///
+/// The value does not need to be aligned in guest memory.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use axvisor_api::vmm::{current_vm_id, read_guest_obj};
+///
+/// let descriptor: [u64; 2] = read_guest_obj(current_vm_id(), desc_gpa)?;
+/// ```
+///
+/// # Errors
+///
+/// See [`read_guest_memory`].
+pub fn read_guest_obj<T: Pod>(vm_id: VMId, gpa: GuestPhysAddr) -> Result<T, GuestMemoryError> {
+    // Zeroed, so the buffer over the value is initialized.
+    let mut value = MaybeUninit::<T>::zeroed();
+    // SAFETY: the buffer covers the value, whose bytes are all initialized.
+    let buf =
+        unsafe { core::slice::from_raw_parts_mut(value.as_mut_ptr().cast::<u8>(), size_of::<T>()) };
+    read_guest_memory(vm_id, gpa, buf)?;
+    // SAFETY: any bit pattern is valid for `Pod`.
+    Ok(unsafe { value.assume_init() })
+}
+
+/// Write a value to the guest physical memory of a VM.
+///
+/// The value does not need to be aligned in guest memory.
+///
+/// # Errors
+///
+/// See [`write_guest_memory`].
+pub fn write_guest_obj<T: Pod>(
+    vm_id: VMId,
+    gpa: GuestPhysAddr,
+    value: &T,
+) -> Result<(), GuestMemoryError> {
+    // SAFETY: `Pod` values have no padding, so all bytes are initialized.
+    let buf =
+        unsafe { core::slice::from_raw_parts((value as *const T).cast::<u8>(), size_of::<T>()) };
+    write_guest_memory(vm_id, gpa, buf)
+}
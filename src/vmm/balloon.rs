@@ -0,0 +1,239 @@
+// Copyright 2025 The Axvisor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Memory ballooning, to reclaim the memory of idle guests.
+//!
+//! The host changes the requested size of the balloon of a VM with
+//! [`inflate`] and [`deflate`], which notify the balloon device of the VM
+//! through [`VmmIf::balloon_target_changed`](super::VmmIf::balloon_target_changed).
+//! The device, e.g. a virtio-balloon frontend, then reports the pages the
+//! guest driver gives up with [`release_pages`], which are unmapped from the
+//! guest and whose frames are returned to the host, and the pages the guest
+//! driver takes back with [`reclaim_pages`], which are backed by newly
+//! allocated frames again:
+//!
+//! ```rust,ignore
+//! use axvisor_api::vmm::balloon;
+//!
+//! // In the host, when the VM is idle
+//! balloon::inflate(vm_id, 0x4000); // 64 MiB
+//!
+//! // In the balloon device, when the guest driver fills the inflate queue
+//! balloon::release_pages(vm_id, &released_gpas)?;
+//! assert_eq!(balloon::size(vm_id), balloon::target(vm_id));
+//! ```
+//!
+//! The pages are unmapped with
+//! [`VmmIf::unmap_guest_page`](super::VmmIf::unmap_guest_page), which
+//! disposes of their frames however the host backs the guest memory, e.g.
+//! deallocating and uncharging them from the VM. Reclaimed pages are backed
+//! by frames allocated with [`accounting::alloc_zeroed_frame_for`], so they
+//! are charged to the VM and do not leak the previous contents of their
+//! frames to the guest, and mapped with
+//! [`VmmIf::map_guest_page`](super::VmmIf::map_guest_page).
+//!
+//! Use [`remove`] to forget a destroyed VM.
+
+extern crate alloc;
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use core::fmt;
+
+use axaddrspace::GuestPhysAddr;
+use memory_addr::MemoryAddr;
+
+use super::{GuestMemoryError, VMId};
+use crate::{
+    memory::{AllocError, accounting},
+    sync::SpinLock,
+};
+
+/// The error returned when a balloon operation fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BalloonError {
+    /// The address is not page-aligned, or the page is already in the
+    /// balloon when released, or not in the balloon when reclaimed.
+    InvalidParam,
+    /// The page could not be unmapped from or mapped into the guest.
+    Guest(GuestMemoryError),
+    /// No frame could be allocated for a reclaimed page, or the memory quota
+    /// of the VM is exhausted.
+    Alloc(AllocError),
+}
+
+impl fmt::Display for BalloonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidParam => write!(f, "invalid parameter"),
+            Self::Guest(err) => write!(f, "guest mapping failed: {err}"),
+            Self::Alloc(err) => write!(f, "allocation failed: {err}"),
+        }
+    }
+}
+
+impl core::error::Error for BalloonError {}
+
+impl From<GuestMemoryError> for BalloonError {
+    fn from(err: GuestMemoryError) -> Self {
+        Self::Guest(err)
+    }
+}
+
+impl From<AllocError> for BalloonError {
+    fn from(err: AllocError) -> Self {
+        Self::Alloc(err)
+    }
+}
+
+/// The balloon of a VM.
+#[derive(Default)]
+struct Balloon {
+    /// The requested number of pages in the balloon.
+    target: usize,
+    /// The guest physical addresses of the pages in the balloon.
+    pages: BTreeSet<GuestPhysAddr>,
+}
+
+/// The balloon of every VM with a target set or pages in the balloon.
+static BALLOONS: SpinLock<BTreeMap<VMId, Balloon>> = SpinLock::new(BTreeMap::new());
+
+/// Change the requested size of the balloon of a VM, and notify its balloon
+/// device if it changed.
+fn update_target(vm_id: VMId, f: impl FnOnce(usize) -> usize) -> usize {
+    let (old, new) = {
+        let mut balloons = BALLOONS.lock();
+        let balloon = balloons.entry(vm_id).or_default();
+        let old = balloon.target;
+        balloon.target = f(old);
+        (old, balloon.target)
+    };
+    if new != old {
+        super::balloon_target_changed(vm_id, new);
+    }
+    new
+}
+
+/// Ask the guest of a VM to give up `num_pages` more pages, returning the
+/// new requested size of the balloon, in pages.
+pub fn inflate(vm_id: VMId, num_pages: usize) -> usize {
+    update_target(vm_id, |target| target.saturating_add(num_pages))
+}
+
+/// Let the guest of a VM take back up to `num_pages` pages, returning the
+/// new requested size of the balloon, in pages.
+pub fn deflate(vm_id: VMId, num_pages: usize) -> usize {
+    update_target(vm_id, |target| target.saturating_sub(num_pages))
+}
+
+/// Get the requested size of the balloon of a VM, in pages.
+pub fn target(vm_id: VMId) -> usize {
+    BALLOONS
+        .lock()
+        .get(&vm_id)
+        .map_or(0, |balloon| balloon.target)
+}
+
+/// Get the number of pages in the balloon of a VM, given up by the guest.
+pub fn size(vm_id: VMId) -> usize {
+    BALLOONS
+        .lock()
+        .get(&vm_id)
+        .map_or(0, |balloon| balloon.pages.len())
+}
+
+/// Whether a guest page of a VM is in the balloon.
+///
+/// Stage-2 fault handlers should not back such pages on demand.
+pub fn contains(vm_id: VMId, gpa: GuestPhysAddr) -> bool {
+    BALLOONS
+        .lock()
+        .get(&vm_id)
+        .is_some_and(|balloon| balloon.pages.contains(&gpa.align_down_4k()))
+}
+
+/// Notify that the guest of a VM gave up pages, which are unmapped from the
+/// guest and their frames returned to the host.
+///
+/// Guests may give up more pages than requested, e.g. with free page
+/// reporting.
+///
+/// # Errors
+///
+/// Stops at the first page which cannot be put into the balloon, in which
+/// case the pages before it are in the balloon.
+pub fn release_pages(vm_id: VMId, gpas: &[GuestPhysAddr]) -> Result<(), BalloonError> {
+    for &gpa in gpas {
+        // Put the page into the balloon first, so concurrent releases of the
+        // same page do not unmap and deallocate it twice.
+        if !gpa.is_aligned_4k() || !BALLOONS.lock().entry(vm_id).or_default().pages.insert(gpa) {
+            return Err(BalloonError::InvalidParam);
+        }
+        if let Err(err) = super::unmap_guest_page(vm_id, gpa) {
+            remove_page(vm_id, gpa);
+            return Err(err.into());
+        }
+    }
+    Ok(())
+}
+
+/// Notify that the guest of a VM takes back pages from the balloon, which
+/// are backed by newly allocated frames charged to the VM.
+///
+/// # Errors
+///
+/// Stops at the first page which cannot be taken out of the balloon, in
+/// which case the pages before it are mapped again.
+pub fn reclaim_pages(vm_id: VMId, gpas: &[GuestPhysAddr]) -> Result<(), BalloonError> {
+    for &gpa in gpas {
+        // Take the page out of the balloon first, so concurrent reclaims of
+        // the same page do not map it twice.
+        if !gpa.is_aligned_4k() || !remove_page(vm_id, gpa) {
+            return Err(BalloonError::InvalidParam);
+        }
+        let result = accounting::alloc_zeroed_frame_for(vm_id)
+            .map_err(BalloonError::Alloc)
+            .and_then(|hpa| {
+                super::map_guest_page(vm_id, gpa, hpa).map_err(|err| {
+                    // SAFETY: the frame was just allocated for the VM, and is
+                    // not mapped.
+                    unsafe { accounting::dealloc_frame_for(vm_id, hpa) };
+                    err.into()
+                })
+            });
+        if let Err(err) = result {
+            BALLOONS.lock().entry(vm_id).or_default().pages.insert(gpa);
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+/// Take a page out of the balloon of a VM, returning whether it was in it.
+fn remove_page(vm_id: VMId, gpa: GuestPhysAddr) -> bool {
+    BALLOONS
+        .lock()
+        .get_mut(&vm_id)
+        .is_some_and(|balloon| balloon.pages.remove(&gpa))
+}
+
+/// Forget the balloon of a VM, returning the number of pages in it.
+///
+/// This should be called when the VM is destroyed.
+pub fn remove(vm_id: VMId) -> usize {
+    BALLOONS
+        .lock()
+        .remove(&vm_id)
+        .map_or(0, |balloon| balloon.pages.len())
+}
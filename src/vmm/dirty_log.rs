@@ -0,0 +1,136 @@
+// Copyright 2025 The Axvisor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A software model of dirty page tracking, for tests and hosts without
+//! hardware support.
+//!
+//! [`SoftDirtyLog`] keeps the dirty pages of every VM with logging enabled in
+//! memory. The host marks pages as dirty with [`SoftDirtyLog::mark_dirty`],
+//! e.g. when it translates a guest page for a write in
+//! [`translate_guest_phys`](super::VmmIf::translate_guest_phys), or when it
+//! handles a stage-2 write fault on a page mapped read-only while logging.
+//! The dirty log functions of [`VmmIf`](super::VmmIf) are forwarded to a
+//! static instance:
+//!
+//! ```rust,ignore
+//! use axvisor_api::vmm::{dirty_log::SoftDirtyLog, *};
+//!
+//! static DIRTY_LOG: SoftDirtyLog = SoftDirtyLog::new();
+//!
+//! struct VmmIfImpl;
+//!
+//! #[axvisor_api::api_impl]
+//! impl VmmIf for VmmIfImpl {
+//!     fn enable_dirty_log(vm_id: VMId) -> Result<(), DirtyLogError> {
+//!         DIRTY_LOG.enable(vm_id)
+//!     }
+//!     // ... forward other functions
+//! }
+//! ```
+
+extern crate alloc;
+
+use alloc::collections::{BTreeMap, BTreeSet};
+
+use axaddrspace::GuestPhysAddr;
+use memory_addr::{MemoryAddr, PAGE_SIZE_4K};
+
+use super::{DirtyBitmap, DirtyLogError, VMId};
+use crate::sync::SpinLock;
+
+/// A software model of dirty page tracking.
+pub struct SoftDirtyLog {
+    /// The numbers of the dirty guest pages of every VM with logging enabled.
+    vms: SpinLock<BTreeMap<VMId, BTreeSet<usize>>>,
+}
+
+impl SoftDirtyLog {
+    /// Create a model with logging disabled for all VMs.
+    pub const fn new() -> Self {
+        Self {
+            vms: SpinLock::new(BTreeMap::new()),
+        }
+    }
+
+    /// Start logging the pages written by a VM, see
+    /// [`enable_dirty_log`](super::enable_dirty_log).
+    pub fn enable(&self, vm_id: VMId) -> Result<(), DirtyLogError> {
+        self.vms.lock().entry(vm_id).or_default();
+        Ok(())
+    }
+
+    /// Stop logging the pages written by a VM, see
+    /// [`disable_dirty_log`](super::disable_dirty_log).
+    pub fn disable(&self, vm_id: VMId) -> Result<(), DirtyLogError> {
+        self.vms
+            .lock()
+            .remove(&vm_id)
+            .map(drop)
+            .ok_or(DirtyLogError::NotEnabled)
+    }
+
+    /// Whether logging is enabled for a VM.
+    pub fn is_enabled(&self, vm_id: VMId) -> bool {
+        self.vms.lock().contains_key(&vm_id)
+    }
+
+    /// Mark the pages overlapping a range written by a VM as dirty. Does
+    /// nothing if logging is not enabled for the VM.
+    pub fn mark_dirty(&self, vm_id: VMId, gpa: GuestPhysAddr, size: usize) {
+        if size == 0 {
+            return;
+        }
+        let mut vms = self.vms.lock();
+        if let Some(pages) = vms.get_mut(&vm_id) {
+            let first = gpa.as_usize() / PAGE_SIZE_4K;
+            let last = gpa.as_usize().saturating_add(size - 1) / PAGE_SIZE_4K;
+            pages.extend(first..=last);
+        }
+    }
+
+    /// Fetch and clear the dirty pages of a range, see
+    /// [`fetch_and_clear_dirty_log`](super::fetch_and_clear_dirty_log).
+    pub fn fetch_and_clear(
+        &self,
+        vm_id: VMId,
+        gpa: GuestPhysAddr,
+        num_pages: usize,
+    ) -> Result<DirtyBitmap, DirtyLogError> {
+        if !gpa.is_aligned_4k() {
+            return Err(DirtyLogError::InvalidParam);
+        }
+        let first = gpa.as_usize() / PAGE_SIZE_4K;
+        let end = num_pages
+            .checked_mul(PAGE_SIZE_4K)
+            .and_then(|size| gpa.as_usize().checked_add(size))
+            .ok_or(DirtyLogError::InvalidParam)?
+            / PAGE_SIZE_4K;
+
+        let mut vms = self.vms.lock();
+        let pages = vms.get_mut(&vm_id).ok_or(DirtyLogError::NotEnabled)?;
+        let mut bitmap = DirtyBitmap::new(gpa, num_pages);
+        let mut dirty = pages.split_off(&first);
+        pages.append(&mut dirty.split_off(&end));
+        for page in dirty {
+            bitmap.set_dirty(page - first);
+        }
+        Ok(bitmap)
+    }
+}
+
+impl Default for SoftDirtyLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}